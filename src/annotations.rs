@@ -0,0 +1,154 @@
+// Line annotations provided by external tools (e.g., coverage or sanitizer reports) via ipc. They
+// are organized in named sets, so that each tool can replace or clear its own annotations.
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use unsegen::base::LineNumber;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "info" => Some(Severity::Info),
+            "warning" => Some(Severity::Warning),
+            "error" => Some(Severity::Error),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Annotation {
+    // Like for breakpoints set via ipc, the full path of the file only has to end with this path.
+    pub file: PathBuf,
+    pub line: LineNumber,
+    pub severity: Severity,
+    pub text: String,
+}
+
+impl Annotation {
+    fn applies_to(&self, file: &Path) -> bool {
+        file.ends_with(&self.file)
+    }
+}
+
+pub struct AnnotationSets {
+    sets: BTreeMap<String, Vec<Annotation>>,
+    pub last_change: Instant,
+}
+
+impl AnnotationSets {
+    pub fn new() -> Self {
+        AnnotationSets {
+            sets: BTreeMap::new(),
+            last_change: Instant::now(),
+        }
+    }
+
+    fn notify_change(&mut self) {
+        self.last_change = Instant::now();
+    }
+
+    // Replaces the set with the given name (if it exists).
+    pub fn set(&mut self, name: String, annotations: Vec<Annotation>) {
+        self.sets.insert(name, annotations);
+        self.notify_change();
+    }
+
+    // Appends to the set with the given name (or creates it) and returns its new size.
+    pub fn add(&mut self, name: String, annotations: Vec<Annotation>) -> usize {
+        let set = self.sets.entry(name).or_insert_with(Vec::new);
+        set.extend(annotations);
+        let len = set.len();
+        self.notify_change();
+        len
+    }
+
+    // Returns whether a set with the given name existed.
+    pub fn clear(&mut self, name: &str) -> bool {
+        let existed = self.sets.remove(name).is_some();
+        self.notify_change();
+        existed
+    }
+
+    pub fn clear_all(&mut self) {
+        self.sets.clear();
+        self.notify_change();
+    }
+
+    pub fn sets(&self) -> impl Iterator<Item = (&String, &Vec<Annotation>)> {
+        self.sets.iter()
+    }
+
+    // All annotations (of all sets) for the given file, sorted by line.
+    pub fn for_file(&self, file: &Path) -> Vec<&Annotation> {
+        let mut annotations = self
+            .sets
+            .values()
+            .flat_map(|set| set.iter())
+            .filter(|annotation| annotation.applies_to(file))
+            .collect::<Vec<_>>();
+        annotations.sort_by_key(|annotation| annotation.line.raw_value());
+        annotations
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn annotation(file: &str, line: usize, severity: Severity) -> Annotation {
+        Annotation {
+            file: PathBuf::from(file),
+            line: LineNumber::new(line),
+            severity: severity,
+            text: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_for_file() {
+        let mut sets = AnnotationSets::new();
+        sets.set(
+            "coverage".to_owned(),
+            vec![
+                annotation("src/main.c", 10, Severity::Info),
+                annotation("src/other.c", 3, Severity::Info),
+            ],
+        );
+        sets.set(
+            "asan".to_owned(),
+            vec![annotation(
+                "/home/user/project/src/main.c",
+                4,
+                Severity::Error,
+            )],
+        );
+        let lines = |sets: &AnnotationSets| {
+            sets.for_file(Path::new("/home/user/project/src/main.c"))
+                .into_iter()
+                .map(|a| (a.line, a.severity))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            lines(&sets),
+            vec![
+                (LineNumber::new(4), Severity::Error),
+                (LineNumber::new(10), Severity::Info)
+            ]
+        );
+        assert!(sets
+            .for_file(Path::new("/home/user/project/src/ain.c"))
+            .is_empty());
+
+        assert!(sets.clear("asan"));
+        assert!(!sets.clear("asan"));
+        assert_eq!(lines(&sets), vec![(LineNumber::new(10), Severity::Info)]);
+    }
+}
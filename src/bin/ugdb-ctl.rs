@@ -0,0 +1,282 @@
+// A command line client for the ipc interface of ugdb: Running instances are discovered via their
+// sockets in the runtime directory. The result of the request is printed as json.
+#[macro_use]
+extern crate json;
+extern crate structopt;
+extern crate unix_socket;
+
+// Not all parts of the protocol are needed by clients.
+#[allow(dead_code)]
+#[path = "../ipc_protocol.rs"]
+mod ipc_protocol;
+
+use ipc_protocol::{
+    is_stale_socket, socket_dir, socket_path_of_metadata, try_read_ipc_message, write_ipc_message,
+    PROTOCOL_VERSION,
+};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+use structopt::StructOpt;
+use unix_socket::UnixStream;
+
+const EXIT_REQUEST_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_IO: i32 = 3;
+
+#[derive(StructOpt)]
+#[structopt(
+    name = "ugdb-ctl",
+    about = "Send requests to a running ugdb instance.",
+    raw(setting = "structopt::clap::AppSettings::TrailingVarArg")
+)]
+struct Options {
+    #[structopt(long = "pid", help = "Select the instance with the given process id.")]
+    pid: Option<u32>,
+    #[structopt(
+        long = "target",
+        help = "Select the instance debugging a program whose path contains the given string."
+    )]
+    target: Option<String>,
+    #[structopt(
+        long = "cwd",
+        help = "Select the instance running in the given working directory.",
+        parse(from_os_str)
+    )]
+    cwd: Option<PathBuf>,
+    #[structopt(
+        long = "socket",
+        help = "Connect to the given socket instead of searching for an instance.",
+        parse(from_os_str)
+    )]
+    socket: Option<PathBuf>,
+    #[structopt(long = "list", help = "List all running instances.")]
+    list: bool,
+    #[structopt(
+        name = "function",
+        help = "The ipc function to call (e.g., set_breakpoint, see doc/ipc-schema.json for all functions) followed by its parameters, either as a single json object or as pairs of \"--name value\". Values are parsed as json if possible and used as strings otherwise.",
+        raw(allow_hyphen_values = "true")
+    )]
+    request: Vec<String>,
+}
+
+enum Error {
+    Usage(String),
+    IO(String),
+    Request(json::JsonValue),
+}
+
+impl Error {
+    fn exit(self) -> ! {
+        match self {
+            Error::Usage(msg) => {
+                eprintln!("ugdb-ctl: {}", msg);
+                process::exit(EXIT_USAGE);
+            }
+            Error::IO(msg) => {
+                eprintln!("ugdb-ctl: {}", msg);
+                process::exit(EXIT_IO);
+            }
+            Error::Request(response) => {
+                println!("{}", response.pretty(2));
+                process::exit(EXIT_REQUEST_FAILED);
+            }
+        }
+    }
+}
+
+struct Instance {
+    socket: PathBuf,
+    info: json::JsonValue,
+}
+
+impl Instance {
+    fn matches(&self, options: &Options) -> bool {
+        if let Some(pid) = options.pid {
+            if self.info["pid"].as_u32() != Some(pid) {
+                return false;
+            }
+        }
+        if let Some(ref target) = options.target {
+            match self.info["target"].as_str() {
+                Some(t) if t.contains(target.as_str()) => {}
+                _ => return false,
+            }
+        }
+        if let Some(ref cwd) = options.cwd {
+            let cwd = fs::canonicalize(cwd).unwrap_or(cwd.clone());
+            match self.info["working_directory"].as_str() {
+                Some(d) if Path::new(d) == cwd => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+fn call(
+    socket: &Path,
+    function: &str,
+    parameters: json::JsonValue,
+) -> Result<json::JsonValue, Error> {
+    let io_error = |e: String| Error::IO(format!("{}: {}", socket.to_string_lossy(), e));
+    let mut connection = UnixStream::connect(socket).map_err(|e| io_error(e.to_string()))?;
+    let request = object! {
+        "function" => function,
+        "parameters" => parameters,
+        "protocol_version" => PROTOCOL_VERSION
+    };
+    write_ipc_message(&mut connection, request.dump().as_bytes())
+        .map_err(|e| io_error(e.to_string()))?;
+    let response = try_read_ipc_message(&mut connection)
+        .map_err(|_| io_error("Malformed or missing response".to_owned()))?;
+    let response = String::from_utf8(response)
+        .ok()
+        .and_then(|r| json::parse(&r).ok())
+        .ok_or_else(|| io_error("Response is not valid json".to_owned()))?;
+    if response["type"] == "success" {
+        Ok(response["result"].clone())
+    } else {
+        Err(Error::Request(response))
+    }
+}
+
+// Instances are described by the metadata files next to their sockets. Sockets left behind by
+// instances that crashed are skipped.
+fn discover_instances() -> Vec<Instance> {
+    let entries = if let Ok(entries) = fs::read_dir(socket_dir()) {
+        entries
+    } else {
+        return Vec::new();
+    };
+    let mut instances = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let socket = socket_path_of_metadata(&entry.path())?;
+            if is_stale_socket(&socket) {
+                return None;
+            }
+            let metadata = fs::read_to_string(entry.path()).ok()?;
+            let info = json::parse(&metadata).ok()?;
+            Some(Instance {
+                socket: socket,
+                info: info,
+            })
+        })
+        .collect::<Vec<_>>();
+    instances.sort_by_key(|i| i.info["pid"].as_u32());
+    instances
+}
+
+fn select_socket(options: &Options) -> Result<PathBuf, Error> {
+    if let Some(ref socket) = options.socket {
+        return Ok(socket.clone());
+    }
+    let mut candidates = discover_instances()
+        .into_iter()
+        .filter(|i| i.matches(options))
+        .collect::<Vec<_>>();
+    match candidates.len() {
+        0 => Err(Error::Usage("No matching ugdb instance found.".to_owned())),
+        1 => Ok(candidates.remove(0).socket),
+        _ => {
+            let pids = candidates
+                .iter()
+                .map(|i| i.info["pid"].to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(Error::Usage(format!(
+                "Multiple ugdb instances found (pids {}). Select one using --pid, --target or --cwd.",
+                pids
+            )))
+        }
+    }
+}
+
+fn parse_parameters(args: &[String]) -> Result<json::JsonValue, Error> {
+    if args.len() == 1 && !args[0].starts_with("--") {
+        return match json::parse(&args[0]) {
+            Ok(ref obj) if obj.is_object() => Ok(obj.clone()),
+            _ => Err(Error::Usage(format!(
+                "Parameters '{}' are not a json object.",
+                args[0]
+            ))),
+        };
+    }
+    let mut parameters = json::JsonValue::new_object();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = if arg.starts_with("--") && arg.len() > 2 {
+            &arg[2..]
+        } else {
+            return Err(Error::Usage(format!(
+                "Expected parameter name (--name), but found '{}'.",
+                arg
+            )));
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| Error::Usage(format!("Missing value for parameter '{}'.", name)))?;
+        parameters[name] = json::parse(value).unwrap_or(value.as_str().into());
+    }
+    Ok(parameters)
+}
+
+fn run(options: Options) -> Result<(), Error> {
+    if options.list {
+        let instances = discover_instances()
+            .iter()
+            .filter(|i| i.matches(&options))
+            .map(|i| i.info.clone())
+            .collect::<Vec<_>>();
+        println!("{}", json::JsonValue::from(instances).pretty(2));
+        return Ok(());
+    }
+    let (function, parameters) = options
+        .request
+        .split_first()
+        .ok_or_else(|| Error::Usage("No function specified. See --help for usage.".to_owned()))?;
+    let parameters = parse_parameters(parameters)?;
+    let socket = select_socket(&options)?;
+    let result = call(&socket, function, parameters)?;
+    println!("{}", result.pretty(2));
+    Ok(())
+}
+
+fn main() {
+    let options = Options::from_args();
+    if let Err(e) = run(options) {
+        e.exit();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn params(args: &[&str]) -> Result<json::JsonValue, ()> {
+        parse_parameters(&args.iter().map(|s| s.to_string()).collect::<Vec<_>>()).map_err(|_| ())
+    }
+
+    #[test]
+    fn test_parse_parameters() {
+        assert_eq!(params(&[]), Ok(object! {}));
+        assert_eq!(
+            params(&[r#"{"file": "main.c", "line": 42}"#]),
+            Ok(object! { "file" => "main.c", "line" => 42 })
+        );
+        assert_eq!(
+            params(&["--file", "main.c", "--line", "42"]),
+            Ok(object! { "file" => "main.c", "line" => 42 })
+        );
+        assert_eq!(
+            params(&["--topics", r#"["stopped"]"#, "--expression", "a + b"]),
+            Ok(object! { "topics" => array!["stopped"], "expression" => "a + b" })
+        );
+
+        assert_eq!(params(&["[1, 2]"]), Err(()));
+        assert_eq!(params(&["--file"]), Err(()));
+        assert_eq!(params(&["file", "main.c"]), Err(()));
+        assert_eq!(params(&["--", "main.c"]), Err(()));
+    }
+}
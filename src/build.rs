@@ -0,0 +1,171 @@
+// Rebuilding the program from within ugdb: The build command (e.g., `cargo build` or `make`) is run
+// in the background with its output shown in the terminal pane. Once it succeeds, the program is
+// reloaded and breakpoints are re-resolved.
+use chan;
+use gdb::ReloadError;
+use gdbmi::commands::MiCommand;
+use gdbmi::ExecuteError;
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus, Stdio};
+use std::thread;
+use tui::commands::restart_program;
+
+#[derive(Debug)]
+pub enum BuildError {
+    NoCommand,
+    AlreadyRunning,
+    Io(io::Error),
+}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &BuildError::NoCommand => write!(
+                f,
+                "No build command configured. Specify one using --build-command."
+            ),
+            &BuildError::AlreadyRunning => write!(f, "A build is already running."),
+            &BuildError::Io(ref e) => write!(f, "{}", e),
+        }
+    }
+}
+
+pub struct BuildResult {
+    status: io::Result<ExitStatus>,
+    restart: bool,
+}
+
+pub struct Build {
+    command: Option<String>,
+    tty: OsString,
+    running: bool,
+    result_sink: chan::Sender<BuildResult>,
+}
+
+impl Build {
+    // The output of the build command is written to `tty`.
+    pub fn new(command: Option<String>, tty: OsString) -> (Self, chan::Receiver<BuildResult>) {
+        let (result_sink, results) = chan::async();
+        (
+            Build {
+                command: command,
+                tty: tty,
+                running: false,
+                result_sink: result_sink,
+            },
+            results,
+        )
+    }
+
+    // Run the build command in the background. The result is sent to the receiver returned by
+    // `new` once the build has finished.
+    fn start(&mut self, restart: bool) -> Result<(), BuildError> {
+        let command = self.command.as_ref().ok_or(BuildError::NoCommand)?;
+        if self.running {
+            return Err(BuildError::AlreadyRunning);
+        }
+        let mut output = OpenOptions::new().write(true).open(&self.tty)?;
+        writeln!(output, "$ {}", command)?;
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(output.try_clone()?)
+            .stderr(output)
+            .spawn()?;
+        let result_sink = self.result_sink.clone();
+        thread::Builder::new()
+            .name("build".to_owned())
+            .spawn(move || {
+                result_sink.send(BuildResult {
+                    status: child.wait(),
+                    restart: restart,
+                });
+            })?;
+        self.running = true;
+        Ok(())
+    }
+}
+
+pub fn start_build(restart: bool, p: ::UpdateParameters) {
+    match p.build.start(restart) {
+        Ok(()) => p
+            .message_sink
+            .send("Building... (see terminal for the output)"),
+        Err(e) => p.message_sink.send(format!("Unable to build: {}", e)),
+    }
+}
+
+impl BuildResult {
+    pub fn handle(self, p: ::UpdateParameters) {
+        p.build.running = false;
+        match self.status {
+            Ok(ref status) if status.success() => {}
+            Ok(status) => {
+                p.message_sink.send(format!("Build failed ({}).", status));
+                return;
+            }
+            Err(e) => {
+                p.message_sink.send(format!("Unable to build: {}", e));
+                return;
+            }
+        }
+        if let Err(e) = self.reload(p) {
+            p.message_sink.send(match e {
+                ExecuteError::Busy => {
+                    "Build succeeded, but the program cannot be reloaded while it is running."
+                }
+                ExecuteError::Quit => "Build succeeded, but gdb has quit.",
+            });
+        }
+    }
+
+    fn reload(&self, p: ::UpdateParameters) -> Result<(), ExecuteError> {
+        let program = if let Some(program) = p.gdb.get_target()? {
+            program
+        } else {
+            p.message_sink
+                .send("Build succeeded, but there is no program to reload.");
+            return Ok(());
+        };
+        // The old process is replaced anyway, so it does not have to be debugged with the
+        // symbols of the new program.
+        if self.restart && p.gdb.mi.is_session_active()? {
+            p.gdb.mi.execute(MiCommand::cli_exec("kill"))?;
+        }
+        match p.gdb.reload_program(&program) {
+            Ok(unresolved) => {
+                p.message_sink.send(format!(
+                    "Build succeeded. Reloaded {}.",
+                    program.to_string_lossy()
+                ));
+                for pos in unresolved {
+                    p.message_sink.send(format!(
+                        "Breakpoint at {}:{} does not resolve anymore and is pending now.",
+                        pos.file.to_string_lossy(),
+                        pos.line
+                    ));
+                }
+            }
+            Err(ReloadError::Execution(e)) => return Err(e),
+            Err(ReloadError::Gdb(msg)) => {
+                p.message_sink
+                    .send(format!("Build succeeded, but reloading failed: {}", msg));
+                return Ok(());
+            }
+        }
+        if self.restart {
+            restart_program(p)?;
+        }
+        Ok(())
+    }
+}
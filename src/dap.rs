@@ -0,0 +1,1085 @@
+// A Debug Adapter Protocol (https://microsoft.github.io/debug-adapter-protocol/) server that allows
+// frontends like VS Code or nvim-dap to use the gdb session of ugdb, either instead of the tui
+// (on stdio) or alongside of it (on a tcp or unix socket).
+use chan;
+use gdb::{BreakpointOperationError, EvaluationError};
+use gdb_expression_parsing::parse_gdb_value;
+use gdbmi::commands::{BreakPointLocation, BreakPointNumber, MiCommand, ValuePrintMode};
+use gdbmi::output::{
+    AsyncClass, JsonValue, Object, OutOfBandRecord, ResultClass, StreamKind, ThreadEvent,
+};
+use gdbmi::ExecuteError;
+use ipc::is_same_user;
+use ipc_protocol::is_stale_socket;
+use json;
+use log::{info, warn};
+use nix::sys::stat::{umask, Mode};
+use std::collections::HashMap;
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::thread;
+use unix_socket::UnixListener;
+
+const CONTENT_LENGTH_HEADER: &'static str = "Content-Length:";
+// Protects against allocating huge buffers for malformed headers.
+const MAX_MESSAGE_LENGTH: usize = 1 << 24;
+// Used as the upper bound if a client requests all frames starting from a given level.
+const MAX_FRAME_LEVEL: u64 = i32::MAX as u64;
+const TOKEN_LENGTH: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DapEndpoint {
+    Stdio,
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl ::std::str::FromStr for DapEndpoint {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "stdio" {
+            Ok(DapEndpoint::Stdio)
+        } else if s.contains('/') {
+            Ok(DapEndpoint::Unix(PathBuf::from(s)))
+        } else if s.contains(':') {
+            Ok(DapEndpoint::Tcp(s.to_owned()))
+        } else {
+            Err(format!(
+                "Invalid DAP endpoint '{}', expected 'stdio', host:port or the path of a unix socket",
+                s
+            ))
+        }
+    }
+}
+
+pub enum DapMessage {
+    Connected(Box<dyn Write + Send>),
+    Request(JsonValue),
+    Disconnected,
+}
+
+fn read_message<R: BufRead>(r: &mut R) -> Result<JsonValue, ()> {
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line).map_err(|_| {})? == 0 {
+            return Err(());
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(CONTENT_LENGTH_HEADER) {
+            content_length = line[CONTENT_LENGTH_HEADER.len()..]
+                .trim()
+                .parse::<usize>()
+                .ok();
+        }
+    }
+    let content_length = content_length.ok_or(())?;
+    if content_length > MAX_MESSAGE_LENGTH {
+        return Err(());
+    }
+    let mut buf = vec![0u8; content_length];
+    r.read_exact(&mut buf).map_err(|_| {})?;
+    let msg = String::from_utf8(buf).map_err(|_| {})?;
+    json::parse(&msg).map_err(|_| {})
+}
+
+fn write_message<W: Write + ?Sized>(w: &mut W, msg: &JsonValue) -> io::Result<()> {
+    let body = msg.dump();
+    write!(
+        w,
+        "{} {}\r\n\r\n{}",
+        CONTENT_LENGTH_HEADER,
+        body.len(),
+        body
+    )?;
+    w.flush()
+}
+
+fn serve_connection<R: Read>(input: R, message_sink: &chan::Sender<DapMessage>) {
+    let mut input = BufReader::new(input);
+    while let Ok(msg) = read_message(&mut input) {
+        message_sink.send(DapMessage::Request(msg));
+    }
+    message_sink.send(DapMessage::Disconnected);
+}
+
+// Clients are served one after another.
+fn serve_connections<S, I>(connections: I, message_sink: chan::Sender<DapMessage>)
+where
+    S: Read + Write + Send + 'static,
+    I: Iterator<Item = io::Result<(S, S)>>,
+{
+    for connection in connections {
+        match connection {
+            Ok((input, output)) => {
+                info!("DAP client connected");
+                message_sink.send(DapMessage::Connected(Box::new(output)));
+                serve_connection(input, &message_sink);
+            }
+            Err(e) => warn!("Unable to accept DAP connection: {}", e),
+        }
+    }
+}
+
+// Replaces a file that has been left behind by an instance that crashed.
+fn write_token_file(path: &Path, token: &str) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(token.as_bytes())
+}
+
+fn execute_error_message(error: ExecuteError) -> String {
+    match error {
+        ExecuteError::Busy => "GDB is busy".to_owned(),
+        ExecuteError::Quit => "GDB quit".to_owned(),
+    }
+}
+
+fn breakpoint_error_message(error: BreakpointOperationError) -> String {
+    match error {
+        BreakpointOperationError::Busy => "GDB is busy".to_owned(),
+        BreakpointOperationError::ExecutionError(msg) => msg,
+    }
+}
+
+fn evaluation_error_message(error: EvaluationError) -> String {
+    match error {
+        EvaluationError::Execution(e) => execute_error_message(e),
+        EvaluationError::Gdb(msg) => msg,
+    }
+}
+
+fn execute(p: ::UpdateParameters, command: MiCommand) -> Result<Object, String> {
+    let result = p.gdb.mi.execute(command).map_err(execute_error_message)?;
+    match result.class {
+        ResultClass::Error => Err(result.results["msg"]
+            .as_str()
+            .unwrap_or("Unknown error")
+            .to_owned()),
+        _ => Ok(result.results),
+    }
+}
+
+// Gdb reports all numbers (e.g., thread ids or lines) as strings.
+fn parse_number(value: &JsonValue) -> Option<u64> {
+    value.as_str().and_then(|s| s.parse::<u64>().ok())
+}
+
+fn parse_value(value: &str) -> JsonValue {
+    parse_gdb_value(value).unwrap_or_else(|_| JsonValue::String(value.to_owned()))
+}
+
+fn has_children(value: &JsonValue) -> bool {
+    (value.is_object() || value.is_array()) && !value.is_empty()
+}
+
+fn children(value: &JsonValue) -> Vec<(String, JsonValue)> {
+    match value {
+        &JsonValue::Object(ref obj) => obj
+            .iter()
+            .map(|(name, value)| (name.to_owned(), value.clone()))
+            .collect(),
+        &JsonValue::Array(ref elements) => elements
+            .iter()
+            .enumerate()
+            .map(|(i, value)| (format!("[{}]", i), value.clone()))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn summarize(value: &JsonValue) -> String {
+    match value {
+        &JsonValue::Object(ref obj) if obj.is_empty() => "{}".to_owned(),
+        &JsonValue::Object(_) => "{...}".to_owned(),
+        &JsonValue::Array(ref elements) => format!("[{} elements]", elements.len()),
+        other => other
+            .as_str()
+            .map(|s| s.to_owned())
+            .unwrap_or_else(|| other.dump()),
+    }
+}
+
+enum Variables {
+    Frame { thread_id: u64, level: u64 },
+    Value(JsonValue),
+}
+
+// Frame ids and variable references that were handed out to the client. They are only valid
+// until execution is resumed.
+struct References {
+    frames: Vec<(u64, u64)>,
+    variables: Vec<Variables>,
+}
+
+impl References {
+    fn new() -> Self {
+        References {
+            frames: Vec::new(),
+            variables: Vec::new(),
+        }
+    }
+
+    fn clear(&mut self) {
+        self.frames.clear();
+        self.variables.clear();
+    }
+
+    // Ids start at 1, because 0 means "no reference" for variables.
+    fn add_frame(&mut self, thread_id: u64, level: u64) -> usize {
+        self.frames.push((thread_id, level));
+        self.frames.len()
+    }
+
+    fn frame(&self, id: usize) -> Result<(u64, u64), String> {
+        id.checked_sub(1)
+            .and_then(|i| self.frames.get(i))
+            .cloned()
+            .ok_or_else(|| format!("Unknown frame id {}", id))
+    }
+
+    fn add_variables(&mut self, variables: Variables) -> usize {
+        self.variables.push(variables);
+        self.variables.len()
+    }
+
+    fn variables(&self, reference: usize) -> Result<&Variables, String> {
+        reference
+            .checked_sub(1)
+            .and_then(|i| self.variables.get(i))
+            .ok_or_else(|| format!("Unknown variables reference {}", reference))
+    }
+
+    fn reference_for(&mut self, value: JsonValue) -> usize {
+        if has_children(&value) {
+            self.add_variables(Variables::Value(value))
+        } else {
+            0
+        }
+    }
+
+    fn to_variable(&mut self, name: String, value: JsonValue) -> JsonValue {
+        object! {
+            "name" => name,
+            "value" => summarize(&value),
+            "variablesReference" => self.reference_for(value)
+        }
+    }
+}
+
+type DapCommand = fn(&mut DapServer, ::UpdateParameters, &JsonValue) -> Result<JsonValue, String>;
+
+const COMMANDS: &'static [(&'static str, DapCommand)] = &[
+    ("initialize", DapServer::initialize),
+    ("launch", DapServer::launch),
+    ("attach", DapServer::attach),
+    ("configurationDone", DapServer::configuration_done),
+    ("disconnect", DapServer::disconnect),
+    ("setBreakpoints", DapServer::set_breakpoints),
+    ("threads", DapServer::threads),
+    ("stackTrace", DapServer::stack_trace),
+    ("scopes", DapServer::scopes),
+    ("variables", DapServer::variables),
+    ("evaluate", DapServer::evaluate),
+    ("continue", DapServer::continue_execution),
+    ("next", DapServer::next),
+    ("stepIn", DapServer::step_in),
+    ("stepOut", DapServer::step_out),
+    ("pause", DapServer::pause),
+];
+
+pub struct DapServer {
+    message_sink: chan::Sender<DapMessage>,
+    output: Option<Box<dyn Write + Send>>,
+    socket_path: Option<PathBuf>,
+    // Tcp clients have to present the token (stored in the token file) when launching or
+    // attaching before they are allowed to control gdb.
+    token: Option<String>,
+    token_path: Option<PathBuf>,
+    authenticated: bool,
+    seq: u64,
+    references: References,
+    // A launched program is only started once the client has finished the configuration (e.g.,
+    // set its breakpoints). The flag specifies whether to stop at the beginning of main.
+    pending_launch: Option<bool>,
+    // Breakpoints set by the client (per source file). Only these are replaced by setBreakpoints,
+    // so that breakpoints set in the TUI are kept.
+    breakpoints: HashMap<PathBuf, Vec<BreakPointNumber>>,
+    attached: bool,
+    ended: bool,
+}
+
+impl DapServer {
+    pub fn new() -> (Self, chan::Receiver<DapMessage>) {
+        let (message_sink, messages) = chan::async();
+        (
+            DapServer {
+                message_sink: message_sink,
+                output: None,
+                socket_path: None,
+                token: None,
+                token_path: None,
+                authenticated: false,
+                seq: 0,
+                references: References::new(),
+                pending_launch: None,
+                breakpoints: HashMap::new(),
+                attached: false,
+                ended: false,
+            },
+            messages,
+        )
+    }
+
+    // The token file for tcp clients is written to `private_dir`.
+    pub fn start(&mut self, endpoint: &DapEndpoint, private_dir: &Path) -> io::Result<()> {
+        let message_sink = self.message_sink.clone();
+        match endpoint {
+            &DapEndpoint::Stdio => {
+                self.output = Some(Box::new(io::stdout()));
+                thread::Builder::new()
+                    .name("DAP stdio".to_owned())
+                    .spawn(move || serve_connection(io::stdin(), &message_sink))?;
+            }
+            &DapEndpoint::Tcp(ref address) => {
+                // The debugger must not be reachable from other machines and local clients have
+                // to authenticate using the token.
+                let addresses = address
+                    .to_socket_addrs()?
+                    .filter(|address| address.ip().is_loopback())
+                    .collect::<Vec<_>>();
+                if addresses.is_empty() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "Only loopback addresses (e.g., 127.0.0.1) can be used for DAP via tcp",
+                    ));
+                }
+                let listener = TcpListener::bind(&addresses[..])?;
+                let token = {
+                    use rand::Rng;
+                    ::rand::thread_rng()
+                        .gen_ascii_chars()
+                        .take(TOKEN_LENGTH)
+                        .collect::<String>()
+                };
+                let token_path =
+                    private_dir.join(format!("dap-{}.token", listener.local_addr()?.port()));
+                write_token_file(&token_path, &token)?;
+                info!("DAP token written to {}", token_path.to_string_lossy());
+                self.token = Some(token);
+                self.token_path = Some(token_path);
+                thread::Builder::new()
+                    .name("DAP Connection Listener".to_owned())
+                    .spawn(move || {
+                        let connections = listener.incoming().map(|connection| {
+                            connection.and_then(|c| c.try_clone().map(|output| (c, output)))
+                        });
+                        serve_connections(connections, message_sink)
+                    })?;
+            }
+            &DapEndpoint::Unix(ref path) => {
+                // Sockets of instances that crashed are not removed.
+                let is_socket = fs::symlink_metadata(path)
+                    .map(|metadata| metadata.file_type().is_socket())
+                    .unwrap_or(false);
+                if is_socket && is_stale_socket(path) {
+                    info!("Removing stale DAP socket {}", path.to_string_lossy());
+                    fs::remove_file(path)?;
+                }
+                // Anyone who can connect can execute arbitrary commands via gdb, so the socket
+                // must not be accessible by other users at any time.
+                let previous_umask = umask(Mode::from_bits_truncate(0o177));
+                let listener = UnixListener::bind(path);
+                umask(previous_umask);
+                let listener = listener?;
+                self.socket_path = Some(path.clone());
+                thread::Builder::new()
+                    .name("DAP Connection Listener".to_owned())
+                    .spawn(move || {
+                        let connections = listener.incoming().map(|connection| {
+                            connection.and_then(|c| {
+                                if is_same_user(&c) {
+                                    c.try_clone().map(|output| (c, output))
+                                } else {
+                                    Err(io::Error::new(
+                                        io::ErrorKind::PermissionDenied,
+                                        "Connection of another user",
+                                    ))
+                                }
+                            })
+                        });
+                        serve_connections(connections, message_sink)
+                    })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Whether the client has disconnected or asked to end the debugging session.
+    pub fn session_ended(&self) -> bool {
+        self.ended
+    }
+
+    pub fn end_session(&mut self) {
+        self.send_event("terminated", JsonValue::Null);
+        self.ended = true;
+    }
+
+    pub fn handle_message(&mut self, message: DapMessage, p: ::UpdateParameters) {
+        match message {
+            DapMessage::Connected(output) => {
+                self.output = Some(output);
+                self.seq = 0;
+                self.references.clear();
+                self.pending_launch = None;
+                self.authenticated = false;
+                self.breakpoints.clear();
+                self.attached = false;
+                self.ended = false;
+            }
+            DapMessage::Request(request) => self.respond(request, p),
+            DapMessage::Disconnected => {
+                info!("DAP client disconnected");
+                self.output = None;
+                self.ended = true;
+            }
+        }
+    }
+
+    fn respond(&mut self, request: JsonValue, p: ::UpdateParameters) {
+        // We do not send any requests, so we do not expect any responses either.
+        if request["type"] != "request" {
+            return;
+        }
+        let command = request["command"].as_str().unwrap_or("").to_owned();
+        let result = self
+            .authenticate(&command, &request["arguments"])
+            .and_then(|()| {
+                COMMANDS
+                    .iter()
+                    .find(|&&(name, _)| name == command)
+                    .ok_or_else(|| format!("Unsupported command '{}'", command))
+            })
+            .and_then(|&(_, function)| function(self, p, &request["arguments"]));
+        let success = result.is_ok();
+        let mut response = object! {
+            "type" => "response",
+            "request_seq" => request["seq"].clone(),
+            "command" => command.as_str(),
+            "success" => success
+        };
+        match result {
+            Ok(JsonValue::Null) => {}
+            Ok(body) => response["body"] = body,
+            Err(msg) => response["message"] = msg.into(),
+        }
+        self.send(response);
+        if success && command == "initialize" {
+            self.send_event("initialized", JsonValue::Null);
+        }
+    }
+
+    fn authenticate(&mut self, command: &str, args: &JsonValue) -> Result<(), String> {
+        let token = match self.token {
+            Some(ref token) if !self.authenticated => token,
+            _ => return Ok(()),
+        };
+        match command {
+            "initialize" => Ok(()),
+            "launch" | "attach" if args["token"].as_str() == Some(token.as_str()) => {
+                self.authenticated = true;
+                Ok(())
+            }
+            "launch" | "attach" => Err("Invalid or missing token".to_owned()),
+            _ => Err("Launch or attach with the token of the session first".to_owned()),
+        }
+    }
+
+    fn send(&mut self, mut msg: JsonValue) {
+        self.seq += 1;
+        msg["seq"] = self.seq.into();
+        let failed = if let Some(ref mut output) = self.output {
+            write_message(output, &msg).is_err()
+        } else {
+            false
+        };
+        if failed {
+            warn!("Unable to write DAP message, dropping client");
+            self.output = None;
+        }
+    }
+
+    fn send_event(&mut self, event: &str, body: JsonValue) {
+        let mut msg = object! {
+            "type" => "event",
+            "event" => event
+        };
+        if !body.is_null() {
+            msg["body"] = body;
+        }
+        self.send(msg);
+    }
+
+    // Output of the program, if it is not shown in the terminal of the tui.
+    pub fn add_program_output(&mut self, output: &[u8]) {
+        let output = String::from_utf8_lossy(output).into_owned();
+        self.send_event(
+            "output",
+            object! {
+                "category" => "stdout",
+                "output" => output
+            },
+        );
+    }
+
+    // Messages that would otherwise be shown in the console of the tui.
+    pub fn add_message(&mut self, message: String) {
+        self.send_event(
+            "output",
+            object! {
+                "category" => "console",
+                "output" => message + "\n"
+            },
+        );
+    }
+
+    pub fn handle_out_of_band_record(&mut self, record: &OutOfBandRecord) {
+        if self.output.is_none() {
+            return;
+        }
+        match record {
+            &OutOfBandRecord::StreamRecord { ref kind, ref data } => {
+                let category = match kind {
+                    &StreamKind::Console => "console",
+                    &StreamKind::Target => "stdout",
+                    // Echo of the commands that were executed
+                    &StreamKind::Log => return,
+                };
+                self.send_event(
+                    "output",
+                    object! {
+                        "category" => category,
+                        "output" => data.as_str()
+                    },
+                );
+            }
+            &OutOfBandRecord::AsyncRecord {
+                ref class,
+                ref results,
+                ..
+            } => match class {
+                &AsyncClass::Stopped => self.handle_stop(results),
+                &AsyncClass::Other(ref class) if class == "running" => {
+                    self.references.clear();
+                    let mut body = object! {
+                        "allThreadsContinued" => true
+                    };
+                    if let Some(thread_id) = parse_number(&results["thread-id"]) {
+                        body["threadId"] = thread_id.into();
+                    }
+                    self.send_event("continued", body);
+                }
+                &AsyncClass::Thread(ThreadEvent::Created)
+                | &AsyncClass::Thread(ThreadEvent::Exited) => {
+                    if let Some(thread_id) = parse_number(&results["id"]) {
+                        let reason = if class == &AsyncClass::Thread(ThreadEvent::Created) {
+                            "started"
+                        } else {
+                            "exited"
+                        };
+                        self.send_event(
+                            "thread",
+                            object! {
+                                "reason" => reason,
+                                "threadId" => thread_id
+                            },
+                        );
+                    }
+                }
+                _ => {}
+            },
+        }
+    }
+
+    fn handle_stop(&mut self, results: &Object) {
+        self.references.clear();
+        let reason = results["reason"].as_str().unwrap_or("");
+        if reason.starts_with("exited") {
+            // Gdb reports the exit code in octal.
+            let exit_code = results["exit-code"]
+                .as_str()
+                .and_then(|code| i64::from_str_radix(code, 8).ok())
+                .unwrap_or(0);
+            self.send_event(
+                "exited",
+                object! {
+                    "exitCode" => exit_code
+                },
+            );
+            self.send_event("terminated", JsonValue::Null);
+            return;
+        }
+        let signal = results["signal-name"].as_str();
+        let reason = match reason {
+            "breakpoint-hit" => "breakpoint",
+            "end-stepping-range" | "function-finished" | "location-reached" => "step",
+            "signal-received" if signal == Some("SIGINT") => "pause",
+            "signal-received" => "exception",
+            "" => "pause",
+            other => other,
+        };
+        let mut body = object! {
+            "reason" => reason,
+            "allThreadsStopped" => true
+        };
+        if let Some(thread_id) = parse_number(&results["thread-id"]) {
+            body["threadId"] = thread_id.into();
+        }
+        if reason == "exception" {
+            if let Some(signal) = signal {
+                body["description"] = signal.into();
+            }
+        }
+        self.send_event("stopped", body);
+    }
+
+    fn initialize(&mut self, _: ::UpdateParameters, _: &JsonValue) -> Result<JsonValue, String> {
+        Ok(object! {
+            "supportsConfigurationDoneRequest" => true,
+            "supportsConditionalBreakpoints" => true,
+            "supportsEvaluateForHovers" => true
+        })
+    }
+
+    // Without a program, the one that has been loaded in gdb (e.g., by the command line) is used.
+    fn launch(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        if let Some(program) = args["program"].as_str() {
+            execute(p, MiCommand::file_exec_and_symbols(Path::new(program)))?;
+            p.gdb.disassembly_cache.invalidate();
+        }
+        if let Some(cwd) = args["cwd"].as_str() {
+            execute(p, MiCommand::environment_cd(Path::new(cwd)))?;
+        }
+        if args["args"].is_array() {
+            let arguments = args["args"]
+                .members()
+                .filter_map(|arg| arg.as_str())
+                .map(OsString::from)
+                .collect();
+            execute(p, MiCommand::exec_arguments(arguments))?;
+        }
+        self.pending_launch = Some(args["stopOnEntry"].as_bool().unwrap_or(false));
+        self.attached = false;
+        Ok(JsonValue::Null)
+    }
+
+    fn attach(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        let pid = args["pid"]
+            .as_u32()
+            .or_else(|| args["processId"].as_u32())
+            .ok_or_else(|| "Missing pid".to_owned())?;
+        execute(p, MiCommand::target_attach(pid))?;
+        self.attached = true;
+        Ok(JsonValue::Null)
+    }
+
+    fn configuration_done(
+        &mut self,
+        p: ::UpdateParameters,
+        _: &JsonValue,
+    ) -> Result<JsonValue, String> {
+        if let Some(stop_on_entry) = self.pending_launch.take() {
+            // The program may have already been started using the tui.
+            if !p
+                .gdb
+                .mi
+                .is_session_active()
+                .map_err(execute_error_message)?
+            {
+                execute(
+                    p,
+                    if stop_on_entry {
+                        MiCommand::exec_start()
+                    } else {
+                        MiCommand::exec_run()
+                    },
+                )?;
+            }
+        }
+        Ok(JsonValue::Null)
+    }
+
+    fn disconnect(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        self.ended = true;
+        let terminate = args["terminateDebuggee"]
+            .as_bool()
+            .unwrap_or(!self.attached);
+        if p.gdb
+            .mi
+            .is_session_active()
+            .map_err(execute_error_message)?
+        {
+            if terminate {
+                execute(p, MiCommand::cli_exec("kill"))?;
+            } else {
+                execute(p, MiCommand::target_detach())?;
+            }
+        }
+        Ok(JsonValue::Null)
+    }
+
+    // The breakpoints of the client replace all breakpoints in the source file.
+    fn set_breakpoints(
+        &mut self,
+        p: ::UpdateParameters,
+        args: &JsonValue,
+    ) -> Result<JsonValue, String> {
+        let file = args["source"]["path"]
+            .as_str()
+            .ok_or_else(|| "Missing source path".to_owned())?;
+        let file = Path::new(file);
+        // The request is validated before the previous breakpoints are deleted.
+        let lines = args["breakpoints"]
+            .members()
+            .map(|bp| {
+                bp["line"]
+                    .as_usize()
+                    .ok_or_else(|| "Missing breakpoint line".to_owned())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // Breakpoints may have been deleted in the TUI in the meantime.
+        let previous = self
+            .breakpoints
+            .remove(file)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|number| p.gdb.breakpoints.contains_key(number))
+            .collect::<Vec<_>>();
+        if !previous.is_empty() {
+            if let Err(e) = p.gdb.delete_breakpoints(previous.iter().cloned()) {
+                self.breakpoints.insert(file.to_owned(), previous);
+                return Err(breakpoint_error_message(e));
+            }
+        }
+        let mut numbers = Vec::new();
+        let mut breakpoints = Vec::new();
+        for (bp, line) in args["breakpoints"].members().zip(lines) {
+            if !bp["hitCondition"].is_null() {
+                breakpoints.push(object! {
+                    "verified" => false,
+                    "line" => line,
+                    "message" => "Hit conditions are not supported"
+                });
+                continue;
+            }
+            let condition = bp["condition"].as_str().filter(|c| !c.is_empty());
+            breakpoints.push(
+                match p.gdb.insert_breakpoint_with_condition(
+                    BreakPointLocation::Line(file, line),
+                    condition,
+                ) {
+                    Ok(number) => {
+                        numbers.push(number);
+                        object! {
+                            "verified" => true,
+                            "line" => line
+                        }
+                    }
+                    Err(e) => object! {
+                        "verified" => false,
+                        "line" => line,
+                        "message" => breakpoint_error_message(e)
+                    },
+                },
+            );
+        }
+        self.breakpoints.insert(file.to_owned(), numbers);
+        Ok(object! {
+            "breakpoints" => breakpoints
+        })
+    }
+
+    fn threads(&mut self, p: ::UpdateParameters, _: &JsonValue) -> Result<JsonValue, String> {
+        let results = execute(p, MiCommand::thread_info(None))?;
+        let threads = results["threads"]
+            .members()
+            .filter_map(|thread| {
+                let id = parse_number(&thread["id"])?;
+                let name = thread["name"]
+                    .as_str()
+                    .or_else(|| thread["target-id"].as_str())
+                    .unwrap_or("");
+                Some(object! {
+                    "id" => id,
+                    "name" => name
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(object! {
+            "threads" => threads
+        })
+    }
+
+    fn stack_trace(
+        &mut self,
+        p: ::UpdateParameters,
+        args: &JsonValue,
+    ) -> Result<JsonValue, String> {
+        let thread_id = args["threadId"]
+            .as_u64()
+            .ok_or_else(|| "Missing threadId".to_owned())?;
+        let start = args["startFrame"].as_u64().unwrap_or(0);
+        let range = match args["levels"].as_u64() {
+            Some(levels) if levels > 0 => Some((start, start + levels - 1)),
+            _ if start > 0 => Some((start, MAX_FRAME_LEVEL)),
+            _ => None,
+        };
+        let results = execute(p, MiCommand::stack_list_frames(range).in_thread(thread_id))?;
+        let mut frames = Vec::new();
+        for frame in results["stack"].members() {
+            let level = parse_number(&frame["level"]).unwrap_or(0);
+            let mut dap_frame = object! {
+                "id" => self.references.add_frame(thread_id, level),
+                "name" => frame["func"].as_str().unwrap_or("??"),
+                "line" => parse_number(&frame["line"]).unwrap_or(0),
+                "column" => 0,
+                "instructionPointerReference" => frame["addr"].clone()
+            };
+            if let Some(path) = frame["fullname"].as_str() {
+                dap_frame["source"] = object! {
+                    "name" => frame["file"].clone(),
+                    "path" => path
+                };
+            }
+            frames.push(dap_frame);
+        }
+        Ok(object! {
+            "stackFrames" => frames
+        })
+    }
+
+    fn scopes(&mut self, _: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        let frame_id = args["frameId"]
+            .as_usize()
+            .ok_or_else(|| "Missing frameId".to_owned())?;
+        let (thread_id, level) = self.references.frame(frame_id)?;
+        let reference = self.references.add_variables(Variables::Frame {
+            thread_id: thread_id,
+            level: level,
+        });
+        Ok(object! {
+            "scopes" => array![object! {
+                "name" => "Locals",
+                "presentationHint" => "locals",
+                "variablesReference" => reference,
+                "expensive" => false
+            }]
+        })
+    }
+
+    // Values are parsed into structured values (like in the expression table), so that members of
+    // structs and elements of arrays can be expanded.
+    fn variables(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        let reference = args["variablesReference"]
+            .as_usize()
+            .ok_or_else(|| "Missing variablesReference".to_owned())?;
+        let variables = match self.references.variables(reference)? {
+            &Variables::Frame { thread_id, level } => {
+                let results = execute(
+                    p,
+                    MiCommand::stack_list_variables(ValuePrintMode::AllValues)
+                        .in_frame(thread_id, level),
+                )?;
+                results["variables"]
+                    .members()
+                    .map(|var| {
+                        (
+                            var["name"].as_str().unwrap_or("").to_owned(),
+                            parse_value(var["value"].as_str().unwrap_or("")),
+                        )
+                    })
+                    .collect::<Vec<_>>()
+            }
+            &Variables::Value(ref value) => children(value),
+        };
+        let variables = variables
+            .into_iter()
+            .map(|(name, value)| self.references.to_variable(name, value))
+            .collect::<Vec<_>>();
+        Ok(object! {
+            "variables" => variables
+        })
+    }
+
+    fn evaluate(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        let expression = args["expression"]
+            .as_str()
+            .ok_or_else(|| "Missing expression".to_owned())?
+            .to_owned();
+        let value = match args["frameId"].as_usize() {
+            Some(frame_id) => {
+                let (thread_id, level) = self.references.frame(frame_id)?;
+                p.gdb
+                    .evaluate_expression_in_frame(expression, thread_id, level)
+            }
+            None => p.gdb.evaluate_expression(expression),
+        }
+        .map_err(evaluation_error_message)?;
+        Ok(object! {
+            "result" => summarize(&value),
+            "variablesReference" => self.references.reference_for(value)
+        })
+    }
+
+    fn resume(p: ::UpdateParameters, command: MiCommand, args: &JsonValue) -> Result<(), String> {
+        let command = match args["threadId"].as_u64() {
+            Some(thread_id) => command.in_thread(thread_id),
+            None => command,
+        };
+        execute(p, command).map(|_| ())
+    }
+
+    fn continue_execution(
+        &mut self,
+        p: ::UpdateParameters,
+        args: &JsonValue,
+    ) -> Result<JsonValue, String> {
+        Self::resume(p, MiCommand::exec_continue(false), args)?;
+        Ok(object! {
+            "allThreadsContinued" => true
+        })
+    }
+
+    fn next(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        Self::resume(p, MiCommand::exec_next(false), args).map(|_| JsonValue::Null)
+    }
+
+    fn step_in(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        Self::resume(p, MiCommand::exec_step(false), args).map(|_| JsonValue::Null)
+    }
+
+    fn step_out(&mut self, p: ::UpdateParameters, args: &JsonValue) -> Result<JsonValue, String> {
+        Self::resume(p, MiCommand::exec_finish(false), args).map(|_| JsonValue::Null)
+    }
+
+    fn pause(&mut self, p: ::UpdateParameters, _: &JsonValue) -> Result<JsonValue, String> {
+        p.gdb
+            .mi
+            .interrupt_execution()
+            .map_err(|e| format!("Unable to interrupt execution: {}", e))?;
+        Ok(JsonValue::Null)
+    }
+}
+
+impl ::std::ops::Drop for DapServer {
+    fn drop(&mut self) {
+        if let Some(ref path) = self.socket_path {
+            let _ = fs::remove_file(path);
+        }
+        if let Some(ref path) = self.token_path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn message(content: &str) -> Vec<u8> {
+        format!("Content-Length: {}\r\n\r\n{}", content.len(), content).into_bytes()
+    }
+
+    #[test]
+    fn test_read_message() {
+        let mut input = message(r#"{"seq": 1, "type": "request", "command": "threads"}"#);
+        input.extend(message("{}"));
+        let mut input = &input[..];
+        assert_eq!(read_message(&mut input).unwrap()["command"], "threads");
+        assert_eq!(read_message(&mut input), Ok(object! {}));
+        assert_eq!(read_message(&mut input), Err(()));
+
+        // Other headers are ignored
+        let mut input = &b"Content-Type: json\r\nContent-Length: 2\r\n\r\n{}"[..];
+        assert_eq!(read_message(&mut input), Ok(object! {}));
+
+        // Missing length
+        assert_eq!(read_message(&mut &b"\r\n{}"[..]), Err(()));
+        // Body is shorter than announced
+        assert_eq!(
+            read_message(&mut &b"Content-Length: 10\r\n\r\n{}"[..]),
+            Err(())
+        );
+        // Not json
+        assert_eq!(read_message(&mut &message("{")[..]), Err(()));
+    }
+
+    #[test]
+    fn test_write_message() {
+        let mut output = Vec::new();
+        write_message(&mut output, &object! { "seq" => 1 }).unwrap();
+        assert_eq!(output, message(r#"{"seq":1}"#));
+        assert_eq!(read_message(&mut &output[..]), Ok(object! { "seq" => 1 }));
+    }
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!("stdio".parse(), Ok(DapEndpoint::Stdio));
+        assert_eq!(
+            "127.0.0.1:4711".parse(),
+            Ok(DapEndpoint::Tcp("127.0.0.1:4711".to_owned()))
+        );
+        assert_eq!(
+            "/tmp/ugdb-dap".parse(),
+            Ok(DapEndpoint::Unix(PathBuf::from("/tmp/ugdb-dap")))
+        );
+        assert!("4711".parse::<DapEndpoint>().is_err());
+    }
+
+    #[test]
+    fn test_references() {
+        let mut references = References::new();
+        assert_eq!(references.reference_for("1".into()), 0);
+        let value = parse_value("{a = 1, b = {2, 3}}");
+        let reference = references.reference_for(value);
+        assert_eq!(reference, 1);
+
+        let variables = match references.variables(reference).unwrap() {
+            &Variables::Value(ref value) => children(value),
+            _ => panic!("Expected value"),
+        };
+        let variables = variables
+            .into_iter()
+            .map(|(name, value)| references.to_variable(name, value))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            variables,
+            vec![
+                object! { "name" => "a", "value" => "1", "variablesReference" => 0 },
+                object! { "name" => "b", "value" => "[2 elements]", "variablesReference" => 2 },
+            ]
+        );
+
+        assert_eq!(references.add_frame(1, 0), 1);
+        assert_eq!(references.frame(1), Ok((1, 0)));
+        assert!(references.frame(0).is_err());
+        references.clear();
+        assert!(references.frame(1).is_err());
+        assert!(references.variables(1).is_err());
+    }
+}
@@ -0,0 +1,278 @@
+// Opening source locations in an external editor: The editor command is run in the foreground,
+// i.e., ugdb hands over the terminal until the editor exits (similar to being suspended via
+// SIGTSTP). In follow mode, another (non-interactive) command is run in the background whenever
+// the program stops, e.g., to make an already running editor show the stop location.
+use nix::errno::Errno;
+use nix::poll::{poll, EventFlags, PollFd};
+use nix::sys::termios::{self, Termios};
+use nix::unistd;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::io::RawFd;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Stdio};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use termion;
+use unsegen::base::LineNumber;
+
+const STDIN: RawFd = 0;
+const STDOUT: RawFd = 1;
+
+// Maximum time that it takes the keyboard thread to notice that it has been paused.
+const PAUSE_POLL_INTERVAL_MS: i32 = 100;
+
+#[derive(Debug)]
+pub enum EditorError {
+    NoFollowCommand,
+}
+
+impl fmt::Display for EditorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &EditorError::NoFollowCommand => write!(
+                f,
+                "No editor follow command configured. Specify one using --editor-follow-command."
+            ),
+        }
+    }
+}
+
+fn to_io_error(e: ::nix::Error) -> io::Error {
+    match e {
+        ::nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+// Replace the placeholders {file} and {line} in the command. The file name is quoted, because the
+// command is interpreted by sh.
+fn command_line(template: &str, file: &Path, line: LineNumber) -> String {
+    template
+        .replace("{line}", &line.to_string())
+        .replace("{file}", &shell_quote(&file.to_string_lossy()))
+}
+
+pub struct Editor {
+    command: String,
+    follow_command: Option<String>,
+    following: bool,
+    pending: Option<String>,
+}
+
+impl Editor {
+    pub fn new(command: String, follow_command: Option<String>) -> Self {
+        Editor {
+            command: command,
+            following: follow_command.is_some(),
+            follow_command: follow_command,
+            pending: None,
+        }
+    }
+
+    // Only the main loop has access to the terminal, so it actually runs the editor once it
+    // retrieves the command via `take_pending`.
+    pub fn open(&mut self, file: &Path, line: LineNumber) {
+        self.pending = Some(command_line(&self.command, file, line));
+    }
+
+    pub fn take_pending(&mut self) -> Option<String> {
+        self.pending.take()
+    }
+
+    // Returns whether follow mode is enabled now.
+    pub fn toggle_follow(&mut self) -> Result<bool, EditorError> {
+        if self.follow_command.is_none() {
+            return Err(EditorError::NoFollowCommand);
+        }
+        self.following = !self.following;
+        Ok(self.following)
+    }
+
+    pub fn follow(&self, file: &Path, line: LineNumber) -> io::Result<()> {
+        let command = match self.follow_command {
+            Some(ref command) if self.following => command_line(command, file, line),
+            _ => return Ok(()),
+        };
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        thread::Builder::new()
+            .name("editor follow".to_owned())
+            .spawn(move || {
+                let _ = child.wait();
+            })?;
+        Ok(())
+    }
+}
+
+#[derive(PartialEq)]
+enum ReaderState {
+    Reading,
+    PauseRequested,
+    Paused,
+    Closed,
+}
+
+// Keyboard input is read in a separate thread which must not consume any input while another
+// program (i.e., the editor) uses the terminal.
+#[derive(Clone)]
+pub struct KeyboardPause {
+    state: Arc<(Mutex<ReaderState>, Condvar)>,
+}
+
+impl KeyboardPause {
+    pub fn new() -> Self {
+        KeyboardPause {
+            state: Arc::new((Mutex::new(ReaderState::Reading), Condvar::new())),
+        }
+    }
+
+    // Blocks until the reader has stopped reading.
+    fn pause(&self) {
+        let (ref lock, ref condvar) = *self.state;
+        let mut state = lock.lock().unwrap();
+        if *state == ReaderState::Reading {
+            *state = ReaderState::PauseRequested;
+        }
+        while *state == ReaderState::PauseRequested {
+            state = condvar.wait(state).unwrap();
+        }
+    }
+
+    fn resume(&self) {
+        let (ref lock, ref condvar) = *self.state;
+        let mut state = lock.lock().unwrap();
+        if *state == ReaderState::Paused {
+            *state = ReaderState::Reading;
+        }
+        condvar.notify_all();
+    }
+
+    fn wait_while_paused(&self) {
+        let (ref lock, ref condvar) = *self.state;
+        let mut state = lock.lock().unwrap();
+        if *state == ReaderState::PauseRequested {
+            *state = ReaderState::Paused;
+            condvar.notify_all();
+        }
+        while *state == ReaderState::Paused {
+            state = condvar.wait(state).unwrap();
+        }
+    }
+
+    fn close(&self) {
+        let (ref lock, ref condvar) = *self.state;
+        *lock.lock().unwrap() = ReaderState::Closed;
+        condvar.notify_all();
+    }
+}
+
+// Stdin for the keyboard thread. The file descriptor is read directly (instead of using the
+// buffered io::Stdin), so that poll sees all input that has not been consumed yet.
+pub struct PausableStdin {
+    pause: KeyboardPause,
+}
+
+impl PausableStdin {
+    pub fn new(pause: KeyboardPause) -> Self {
+        PausableStdin { pause: pause }
+    }
+}
+
+impl Read for PausableStdin {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            self.pause.wait_while_paused();
+            let mut fds = [PollFd::new(STDIN, EventFlags::POLLIN)];
+            match poll(&mut fds, PAUSE_POLL_INTERVAL_MS) {
+                Ok(0) | Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Ok(_) => return unistd::read(STDIN, buf).map_err(to_io_error),
+                Err(e) => return Err(to_io_error(e)),
+            }
+        }
+    }
+}
+
+impl Drop for PausableStdin {
+    fn drop(&mut self) {
+        // Nobody has to wait for the reader to pause anymore.
+        self.pause.close();
+    }
+}
+
+// Run the editor command with full access to the terminal: The keyboard thread is paused, the
+// terminal is switched to the main screen and to its original mode (`orig_attr`) and everything
+// is set up again once the command exits. The next Terminal::present redraws the whole screen.
+pub fn run_in_foreground(
+    command: &str,
+    orig_attr: Option<&Termios>,
+    keyboard: &KeyboardPause,
+) -> io::Result<ExitStatus> {
+    keyboard.pause();
+    let result = hand_over_terminal(command, orig_attr);
+    keyboard.resume();
+    result
+}
+
+fn hand_over_terminal(command: &str, orig_attr: Option<&Termios>) -> io::Result<ExitStatus> {
+    let tui_attr = termios::tcgetattr(STDOUT).map_err(to_io_error)?;
+    // The terminal is locked by the unsegen terminal of this thread, which does not prevent us
+    // from writing to it.
+    let mut stdout = io::stdout();
+    write!(
+        stdout,
+        "{}{}",
+        termion::screen::ToMainScreen,
+        termion::cursor::Show
+    )?;
+    stdout.flush()?;
+    if let Some(orig_attr) = orig_attr {
+        termios::tcsetattr(STDOUT, termios::SetArg::TCSANOW, orig_attr).map_err(to_io_error)?;
+    }
+
+    let status = Command::new("sh").arg("-c").arg(command).status();
+
+    termios::tcsetattr(STDOUT, termios::SetArg::TCSANOW, &tui_attr).map_err(to_io_error)?;
+    write!(
+        stdout,
+        "{}{}",
+        termion::screen::ToAlternateScreen,
+        termion::cursor::Hide
+    )?;
+    stdout.flush()?;
+    status
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command_line() {
+        assert_eq!(
+            command_line(
+                "$EDITOR +{line} {file}",
+                Path::new("/src/main.rs"),
+                LineNumber::new(42)
+            ),
+            "$EDITOR +42 '/src/main.rs'"
+        );
+        assert_eq!(
+            command_line(
+                "code -g {file}:{line}",
+                Path::new("/it's {line}.c"),
+                LineNumber::new(1)
+            ),
+            "code -g '/it'\\''s {line}.c':1"
+        );
+    }
+}
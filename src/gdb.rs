@@ -1,16 +1,20 @@
 // This module encapsulates some functionality of gdb. Depending on how general this turns out, we
 // may want to move it to a separate crate or merge it with gdbmi-rs
+use gdb_expression_parsing::parse_gdb_value;
 use gdbmi;
-use gdbmi::commands::{BreakPointLocation, BreakPointNumber, MiCommand};
+use gdbmi::commands::{
+    BreakPointLocation, BreakPointNumber, DisassembleMode, MiCommand, TargetType,
+};
 use gdbmi::output::{BreakPointEvent, JsonValue, Object, ResultClass};
 use gdbmi::ExecuteError;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
 use std::fmt;
 use std::ops::{Add, Sub};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use unsegen::base::LineNumber;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct SrcPosition {
     pub file: PathBuf,
     pub line: LineNumber,
@@ -114,6 +118,8 @@ impl BreakPoint {
 pub struct BreakPointSet {
     map: HashMap<BreakPointNumber, BreakPoint>,
     pub last_change: ::std::time::Instant,
+    // Changes since the last call to drain_changes (e.g., to be reported to ipc clients).
+    changes: Vec<(BreakPointEvent, BreakPointNumber)>,
 }
 
 impl BreakPointSet {
@@ -121,6 +127,7 @@ impl BreakPointSet {
         BreakPointSet {
             map: HashMap::new(),
             last_change: ::std::time::Instant::now(),
+            changes: Vec::new(),
         }
     }
 
@@ -129,18 +136,30 @@ impl BreakPointSet {
     }
 
     pub fn update_breakpoint(&mut self, new_bp: BreakPoint) {
-        let _ = self.map.insert(new_bp.number, new_bp);
+        let number = new_bp.number;
+        let event = if self.map.insert(number, new_bp).is_some() {
+            BreakPointEvent::Modified
+        } else {
+            BreakPointEvent::Created
+        };
         //debug_assert!(res.is_some(), "Modified non-existent breakpoint");
+        self.changes.push((event, number));
         self.notify_change();
     }
 
     pub fn remove_breakpoint(&mut self, bp_num: BreakPointNumber) {
-        self.map.remove(&bp_num);
+        if self.map.remove(&bp_num).is_some() {
+            self.changes.push((BreakPointEvent::Deleted, bp_num));
+        }
         if bp_num.minor.is_none() {
             //TODO: ensure removal of child breakpoints
         }
         self.notify_change();
     }
+
+    pub fn drain_changes(&mut self) -> Vec<(BreakPointEvent, BreakPointNumber)> {
+        ::std::mem::replace(&mut self.changes, Vec::new())
+    }
 }
 
 impl ::std::ops::Deref for BreakPointSet {
@@ -151,9 +170,207 @@ impl ::std::ops::Deref for BreakPointSet {
     }
 }
 
+// Disassembling code without debug information requires a lot of round trips to gdb, but the
+// results do not change unless code is (re)loaded.
+pub struct DisassemblyCache {
+    function_ranges: BTreeMap<Address, Address>,
+    blocks: HashMap<(Address, Address, u8), Vec<JsonValue>>,
+}
+
+impl DisassemblyCache {
+    pub fn new() -> Self {
+        DisassemblyCache {
+            function_ranges: BTreeMap::new(),
+            blocks: HashMap::new(),
+        }
+    }
+
+    pub fn function_range_containing(&self, address: Address) -> Option<(Address, Address)> {
+        self.function_ranges
+            .range(..=address)
+            .next_back()
+            .and_then(|(&begin, &end)| {
+                if address < end {
+                    Some((begin, end))
+                } else {
+                    None
+                }
+            })
+    }
+
+    pub fn insert_function_range(&mut self, begin: Address, end: Address) {
+        self.function_ranges.insert(begin, end);
+    }
+
+    pub fn block(
+        &self,
+        begin: Address,
+        end: Address,
+        mode: DisassembleMode,
+    ) -> Option<&Vec<JsonValue>> {
+        self.blocks.get(&(begin, end, mode as u8))
+    }
+
+    pub fn insert_block(
+        &mut self,
+        begin: Address,
+        end: Address,
+        mode: DisassembleMode,
+        lines: Vec<JsonValue>,
+    ) {
+        self.blocks.insert((begin, end, mode as u8), lines);
+    }
+
+    pub fn invalidate(&mut self) {
+        self.function_ranges.clear();
+        self.blocks.clear();
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordState {
+    Off,
+    Recording,
+    Replaying,
+}
+
+// Echoed after the answer to `info record` to find the end of the (variable length) answer.
+const RECORD_INFO_END_MARKER: &str = "ugdb: end of record info";
+
+// Gdb does not report whether a stop is part of a replay of the recorded history, so we ask it via
+// `info record` after each stop while recording. This also covers reverse execution commands that
+// have been issued from the console.
+pub struct ExecutionRecord {
+    pub state: RecordState,
+    pub method: Option<String>,
+    // Position of the most recent stop that was not part of a replay.
+    pub live_address: Option<Address>,
+    pub live_src_position: Option<SrcPosition>,
+    // Replay targets like rr do not have a live position.
+    pub replay_only: bool,
+    pub current_event: Option<u64>,
+    pub current_tick: Option<u64>,
+    pending_progress_lines: usize,
+    // Whether the answer to a pending `info record` query reported a replay so far
+    pending_record_info: Option<bool>,
+    stop_address: Option<Address>,
+    stop_src_position: Option<SrcPosition>,
+}
+
+impl ExecutionRecord {
+    pub fn new() -> Self {
+        ExecutionRecord {
+            state: RecordState::Off,
+            method: None,
+            live_address: None,
+            live_src_position: None,
+            replay_only: false,
+            current_event: None,
+            current_tick: None,
+            pending_progress_lines: 0,
+            pending_record_info: None,
+            stop_address: None,
+            stop_src_position: None,
+        }
+    }
+
+    pub fn start_replay_session(&mut self, method: &str) {
+        self.state = RecordState::Replaying;
+        self.method = Some(method.to_owned());
+        self.replay_only = true;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.state == RecordState::Replaying
+    }
+
+    pub fn handle_record_started(&mut self, results: &Object) {
+        self.state = RecordState::Recording;
+        self.method = results["method"].as_str().map(|s| s.to_owned());
+    }
+
+    pub fn handle_record_stopped(&mut self) {
+        self.state = RecordState::Off;
+        self.method = None;
+    }
+
+    // Returns whether the output was the answer to a progress or record state query and should not
+    // be displayed.
+    pub fn handle_console_output(&mut self, output: &str) -> bool {
+        if let Some(replaying) = self.pending_record_info {
+            if output.trim() == RECORD_INFO_END_MARKER {
+                self.pending_record_info = None;
+                self.set_replaying(replaying);
+            } else if output.contains("Replay mode:") || output.contains("Replay in progress") {
+                // Full recording and btrace, respectively
+                self.pending_record_info = Some(true);
+            }
+            return true;
+        }
+        if self.pending_progress_lines == 0 {
+            return false;
+        }
+        let parse = |prefix: &str| {
+            if output.starts_with(prefix) {
+                output[prefix.len()..].trim().parse::<u64>().ok()
+            } else {
+                None
+            }
+        };
+        if let Some(event) = parse("Current event:") {
+            self.current_event = Some(event);
+        } else if let Some(tick) = parse("Current tick:") {
+            self.current_tick = Some(tick);
+        } else {
+            return false;
+        }
+        self.pending_progress_lines -= 1;
+        true
+    }
+
+    pub fn handle_stop(&mut self, results: &Object) {
+        let frame = &results["frame"];
+        self.stop_address = frame["addr"]
+            .as_str()
+            .and_then(|addr| Address::parse(addr).ok());
+        self.stop_src_position = match (frame["fullname"].as_str(), frame["line"].as_str()) {
+            (Some(file), Some(line)) => line
+                .parse::<usize>()
+                .ok()
+                .map(|line| SrcPosition::new(PathBuf::from(file), LineNumber::new(line))),
+            _ => None,
+        };
+        // Otherwise, the live position is updated once gdb reported the record state.
+        if self.state == RecordState::Off {
+            self.set_replaying(false);
+        }
+    }
+
+    fn set_replaying(&mut self, replaying: bool) {
+        if self.state != RecordState::Off && !self.replay_only {
+            self.state = if replaying {
+                RecordState::Replaying
+            } else {
+                RecordState::Recording
+            };
+        }
+        if !replaying {
+            self.live_address = self.stop_address;
+            self.live_src_position = self.stop_src_position.clone();
+        }
+    }
+
+    // Whether the record state has to be queried from gdb after a stop.
+    pub fn needs_state_query(&self) -> bool {
+        self.state != RecordState::Off && !self.replay_only
+    }
+}
+
 pub struct GDB {
     pub mi: gdbmi::GDB,
     pub breakpoints: BreakPointSet,
+    pub disassembly_cache: DisassemblyCache,
+    pub record: ExecutionRecord,
 }
 
 pub enum BreakpointOperationError {
@@ -161,11 +378,49 @@ pub enum BreakpointOperationError {
     ExecutionError(String),
 }
 
+pub enum EvaluationError {
+    Execution(ExecuteError),
+    Gdb(String),
+}
+
+pub enum ConnectionError {
+    Execution(ExecuteError),
+    Gdb(String),
+}
+
+pub enum ReloadError {
+    Execution(ExecuteError),
+    Gdb(String),
+}
+
+// Major numbers of the breakpoints in the body of a `-break-list` result that do not have any
+// locations. (Older gdb versions list a breakpoint with multiple locations as an array of the
+// breakpoint followed by its locations.)
+fn pending_breakpoints(body: &JsonValue) -> HashSet<usize> {
+    let mut pending = HashSet::new();
+    for entry in body.members() {
+        for bkpt in Some(entry).into_iter().chain(entry.members()) {
+            if bkpt["pending"].is_null() {
+                continue;
+            }
+            if let Some(number) = bkpt["number"]
+                .as_str()
+                .and_then(|n| n.parse::<BreakPointNumber>().ok())
+            {
+                pending.insert(number.major);
+            }
+        }
+    }
+    pending
+}
+
 impl GDB {
     pub fn new(mi: gdbmi::GDB) -> Self {
         GDB {
             mi: mi,
             breakpoints: BreakPointSet::new(),
+            disassembly_cache: DisassemblyCache::new(),
+            record: ExecutionRecord::new(),
         }
     }
 
@@ -178,17 +433,52 @@ impl GDB {
         &mut self,
         location: BreakPointLocation,
     ) -> Result<(), BreakpointOperationError> {
-        let bp_result = self
-            .mi
-            .execute(&MiCommand::insert_breakpoint(location))
-            .map_err(|e| match e {
-                ExecuteError::Busy => BreakpointOperationError::Busy,
-                ExecuteError::Quit => panic!("Could not insert breakpoint: GDB quit"),
-            })?;
+        self.execute_breakpoint_insertion(MiCommand::insert_breakpoint(location))
+            .map(|_| ())
+    }
+
+    // Returns the number of the breakpoint, so that it can be removed if it is not hit.
+    pub fn insert_temporary_breakpoint(
+        &mut self,
+        location: BreakPointLocation,
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        self.execute_breakpoint_insertion(MiCommand::insert_temporary_breakpoint(location))
+    }
+
+    // Returns the number of the breakpoint, so that clients (e.g., of DAP) can keep track of the
+    // breakpoints that they have set.
+    pub fn insert_breakpoint_with_condition(
+        &mut self,
+        location: BreakPointLocation,
+        condition: Option<&str>,
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        let command = match condition {
+            Some(condition) => MiCommand::insert_conditional_breakpoint(location, condition),
+            None => MiCommand::insert_breakpoint(location),
+        };
+        self.execute_breakpoint_insertion(command)
+    }
+
+    fn execute_breakpoint_insertion(
+        &mut self,
+        command: MiCommand,
+    ) -> Result<BreakPointNumber, BreakpointOperationError> {
+        let bp_result = self.mi.execute(&command).map_err(|e| match e {
+            ExecuteError::Busy => BreakpointOperationError::Busy,
+            ExecuteError::Quit => panic!("Could not insert breakpoint: GDB quit"),
+        })?;
         match bp_result.class {
             ResultClass::Done => {
                 self.handle_breakpoint_event(BreakPointEvent::Created, &bp_result.results);
-                Ok(())
+                bp_result.results["bkpt"]["number"]
+                    .as_str()
+                    .and_then(|n| n.parse::<BreakPointNumber>().ok())
+                    .ok_or_else(|| {
+                        BreakpointOperationError::ExecutionError(format!(
+                            "Missing breakpoint number: {}",
+                            bp_result.results.dump()
+                        ))
+                    })
             }
             ResultClass::Error => Err(BreakpointOperationError::ExecutionError(
                 bp_result
@@ -284,6 +574,157 @@ impl GDB {
         }
     }
 
+    // Evaluate the expression and parse the result into a structured value. Values that cannot be
+    // parsed are returned as an (annotated) string.
+    pub fn evaluate_expression(
+        &mut self,
+        expression: String,
+    ) -> Result<JsonValue, EvaluationError> {
+        self.evaluate(MiCommand::data_evaluate_expression(expression))
+    }
+
+    // Like evaluate_expression, but in the context of the given frame instead of the selected one.
+    pub fn evaluate_expression_in_frame(
+        &mut self,
+        expression: String,
+        thread_id: u64,
+        frame_level: u64,
+    ) -> Result<JsonValue, EvaluationError> {
+        self.evaluate(
+            MiCommand::data_evaluate_expression(expression).in_frame(thread_id, frame_level),
+        )
+    }
+
+    fn evaluate(&mut self, command: MiCommand) -> Result<JsonValue, EvaluationError> {
+        let res = self
+            .mi
+            .execute(command)
+            .map_err(EvaluationError::Execution)?;
+        match res.class {
+            ResultClass::Error => Err(EvaluationError::Gdb(
+                res.results["msg"]
+                    .as_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| res.results.dump()),
+            )),
+            ResultClass::Done => {
+                let to_parse = res.results["value"].as_str().expect("value present");
+                match parse_gdb_value(to_parse) {
+                    Ok(p) => Ok(p),
+                    Err(_) => Ok(JsonValue::String(format!("*Error parsing*: {}", to_parse))),
+                }
+            }
+            other => panic!("unexpected result class: {:?}", other),
+        }
+    }
+
+    pub fn connect_to_target(
+        &mut self,
+        target_type: TargetType,
+        address: &OsStr,
+    ) -> Result<(), ConnectionError> {
+        let res = self
+            .mi
+            .execute(MiCommand::target_select(target_type, address))
+            .map_err(ConnectionError::Execution)?;
+        match res.class {
+            // Older versions of gdb report "connected", newer ones "done".
+            ResultClass::Connected | ResultClass::Done => {
+                // The code on the target is most likely different from the local one.
+                self.disassembly_cache.invalidate();
+                Ok(())
+            }
+            ResultClass::Error => Err(ConnectionError::Gdb(
+                res.results["msg"]
+                    .as_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| res.results.dump()),
+            )),
+            other => Err(ConnectionError::Gdb(format!(
+                "Unexpected result class: {:?}",
+                other
+            ))),
+        }
+    }
+
+    // Load the (rebuilt) program. Gdb re-sets all breakpoints from their original specification
+    // (keeping conditions, commands etc.), so we only have to find out which of them do not
+    // resolve anymore and are pending now. Returns their previous positions.
+    pub fn reload_program(&mut self, program: &Path) -> Result<Vec<SrcPosition>, ReloadError> {
+        let res = self
+            .mi
+            .execute(MiCommand::file_exec_and_symbols(program))
+            .map_err(ReloadError::Execution)?;
+        if res.class == ResultClass::Error {
+            return Err(ReloadError::Gdb(
+                res.results["msg"]
+                    .as_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| res.results.dump()),
+            ));
+        }
+        self.disassembly_cache.invalidate();
+
+        let res = self
+            .mi
+            .execute(MiCommand::break_list())
+            .map_err(ReloadError::Execution)?;
+        if res.class == ResultClass::Error {
+            return Err(ReloadError::Gdb(
+                res.results["msg"]
+                    .as_str()
+                    .map(|s| s.to_owned())
+                    .unwrap_or_else(|| res.results.dump()),
+            ));
+        }
+        // The notifications about the re-set breakpoints have not been handled yet, so
+        // self.breakpoints still describes the breakpoints in the old program.
+        let pending = pending_breakpoints(&res.results["BreakpointTable"]["body"]);
+        let mut unresolved = Vec::new();
+        for bp in self.breakpoints.values() {
+            if let Some(ref pos) = bp.src_pos {
+                if pending.contains(&bp.number.major) && !unresolved.contains(pos) {
+                    unresolved.push(pos.clone());
+                }
+            }
+        }
+        unresolved.sort_by_key(|pos| {
+            let line: usize = pos.line.into();
+            (pos.file.clone(), line)
+        });
+        Ok(unresolved)
+    }
+
+    // rr reports the position within the replay via monitor commands. The answers are written to
+    // the console and picked up by ExecutionRecord::handle_console_output.
+    pub fn query_replay_progress(&mut self) -> Result<(), ExecuteError> {
+        self.record.pending_progress_lines = 0;
+        for command in &["monitor when", "monitor when-ticks"] {
+            if self.mi.execute(MiCommand::cli_exec(command))?.class == ResultClass::Done {
+                self.record.pending_progress_lines += 1;
+            }
+        }
+        Ok(())
+    }
+
+    // The answer is written to the console and picked up by ExecutionRecord::handle_console_output.
+    pub fn query_record_state(&mut self) -> Result<(), ExecuteError> {
+        self.record.pending_record_info = None;
+        if self.mi.execute(MiCommand::cli_exec("info record"))?.class == ResultClass::Done
+            && self
+                .mi
+                .execute(MiCommand::cli_exec(&format!(
+                    "echo {}\\n",
+                    RECORD_INFO_END_MARKER
+                )))?
+                .class
+                == ResultClass::Done
+        {
+            self.record.pending_record_info = Some(false);
+        }
+        Ok(())
+    }
+
     // Warning: This is a hack, as gdbmi does not currently offer a command to query the current target
     // May not work and can break at any time.
     pub fn get_target(&mut self) -> Result<Option<PathBuf>, ExecuteError> {
@@ -372,3 +813,38 @@ pub mod response {
         })?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use json;
+
+    #[test]
+    fn test_pending_breakpoints() {
+        // As parsed from the result of -break-list: A conditional breakpoint (with an ignore count
+        // and commands) that still resolves, a temporary breakpoint that is pending now and a
+        // breakpoint with multiple locations.
+        let body = json::parse(
+            r#"[
+                {"number": "1", "type": "breakpoint", "disp": "keep", "enabled": "y",
+                 "addr": "0x0000000000001139", "func": "main", "file": "a.c",
+                 "fullname": "/tmp/a.c", "line": "5", "cond": "i > 3", "times": "0",
+                 "ignore": "2", "script": ["print i"], "original-location": "/tmp/a.c:5"},
+                {"number": "2", "type": "breakpoint", "disp": "del", "enabled": "y",
+                 "addr": "<PENDING>", "pending": "/tmp/a.c:42", "times": "0",
+                 "original-location": "/tmp/a.c:42"},
+                [
+                    {"number": "3", "type": "breakpoint", "disp": "keep", "enabled": "y",
+                     "addr": "<MULTIPLE>", "times": "0", "original-location": "inlined"},
+                    {"number": "3.1", "enabled": "y", "addr": "0x1150", "func": "main",
+                     "file": "a.c", "fullname": "/tmp/a.c", "line": "7"},
+                    {"number": "3.2", "enabled": "y", "addr": "0x1170", "func": "f",
+                     "file": "a.c", "fullname": "/tmp/a.c", "line": "7"}
+                ]
+            ]"#,
+        )
+        .unwrap();
+        let pending = pending_breakpoints(&body);
+        assert_eq!(pending, [2].iter().cloned().collect());
+    }
+}
@@ -1,4 +1,4 @@
-use std::ffi::OsString;
+use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::io::{Error, Write};
 use std::path::Path;
@@ -10,6 +10,7 @@ pub struct MiCommand {
     parameters: Vec<OsString>,
 }
 
+#[derive(Clone, Copy)]
 pub enum DisassembleMode {
     DisassemblyOnly = 0,
     DisassemblyWithRawOpcodes = 1,
@@ -17,12 +18,68 @@ pub enum DisassembleMode {
     MixedSourceAndDisassemblyWithRawOpcodes = 5,
 }
 
+pub enum ValuePrintMode {
+    NoValues = 0,
+    AllValues = 1,
+    SimpleValues = 2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TargetType {
+    Remote,
+    ExtendedRemote,
+}
+
+impl TargetType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            &TargetType::Remote => "remote",
+            &TargetType::ExtendedRemote => "extended-remote",
+        }
+    }
+}
+
+impl ::std::str::FromStr for TargetType {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "remote" => Ok(TargetType::Remote),
+            "extended-remote" => Ok(TargetType::ExtendedRemote),
+            other => Err(format!(
+                "Invalid target type '{}', expected 'remote' or 'extended-remote'",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
 pub enum BreakPointLocation<'a> {
     Address(usize),
     Function(&'a Path, &'a str),
     Line(&'a Path, usize),
 }
 
+impl<'a> BreakPointLocation<'a> {
+    // Location in the "linespec" format that is expected by commands other than break-insert.
+    fn linespec(&self) -> OsString {
+        match self {
+            &BreakPointLocation::Address(addr) => OsString::from(format!("*0x{:x}", addr)),
+            &BreakPointLocation::Function(path, func_name) => {
+                let mut spec = OsString::from(path);
+                spec.push(":");
+                spec.push(func_name);
+                spec
+            }
+            &BreakPointLocation::Line(path, line_number) => {
+                let mut spec = OsString::from(path);
+                spec.push(format!(":{}", line_number));
+                spec
+            }
+        }
+    }
+}
+
 #[derive(Copy, Clone, Hash, PartialEq, Eq)]
 pub struct BreakPointNumber {
     pub major: usize,
@@ -82,6 +139,21 @@ fn escape_command(input: &str) -> String {
 }
 
 impl MiCommand {
+    // Execute the command in the context of the given thread without changing the thread that is
+    // selected in gdb.
+    pub fn in_thread(mut self, thread_id: u64) -> MiCommand {
+        self.options
+            .splice(0..0, vec!["--thread".into(), thread_id.to_string().into()]);
+        self
+    }
+
+    // Like in_thread, but also in the context of the given frame of the thread.
+    pub fn in_frame(mut self, thread_id: u64, frame_level: u64) -> MiCommand {
+        self.options
+            .splice(0..0, vec!["--frame".into(), frame_level.to_string().into()]);
+        self.in_thread(thread_id)
+    }
+
     pub fn write_interpreter_string<S: Write>(
         &self,
         sink: &mut S,
@@ -155,6 +227,29 @@ impl MiCommand {
         }
     }
 
+    pub fn data_list_register_names() -> MiCommand {
+        MiCommand {
+            operation: "data-list-register-names",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    // Values are formatted in hexadecimal.
+    pub fn data_list_register_values(register_numbers: &[usize]) -> MiCommand {
+        let mut options = vec![OsString::from("x")];
+        options.extend(
+            register_numbers
+                .iter()
+                .map(|n| OsString::from(n.to_string())),
+        );
+        MiCommand {
+            operation: "data-list-register-values",
+            options: options,
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn data_evaluate_expression(expression: String) -> MiCommand {
         MiCommand {
             operation: "data-evaluate-expression",
@@ -189,6 +284,31 @@ impl MiCommand {
         }
     }
 
+    pub fn insert_temporary_breakpoint(location: BreakPointLocation) -> MiCommand {
+        let mut command = Self::insert_breakpoint(location);
+        command.options.insert(0, OsString::from("-t"));
+        command
+    }
+
+    pub fn insert_conditional_breakpoint(
+        location: BreakPointLocation,
+        condition: &str,
+    ) -> MiCommand {
+        let mut command = Self::insert_breakpoint(location);
+        command
+            .options
+            .splice(0..0, vec!["-c".into(), escape_command(condition).into()]);
+        command
+    }
+
+    pub fn break_list() -> MiCommand {
+        MiCommand {
+            operation: "break-list",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn delete_breakpoints<I: Iterator<Item = BreakPointNumber>>(
         breakpoint_numbers: I,
     ) -> MiCommand {
@@ -206,6 +326,14 @@ impl MiCommand {
         }
     }
 
+    pub fn environment_cd(directory: &Path) -> MiCommand {
+        MiCommand {
+            operation: "environment-cd",
+            options: vec![directory.into()],
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn environment_pwd() -> MiCommand {
         MiCommand {
             operation: "environment-pwd",
@@ -235,6 +363,71 @@ impl MiCommand {
         }
     }
 
+    // Note: Execution is resumed at the given location, so you probably want to set a (temporary)
+    // breakpoint there first.
+    pub fn exec_jump(location: BreakPointLocation) -> MiCommand {
+        MiCommand {
+            operation: "exec-jump",
+            options: vec![location.linespec()],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn exec_run() -> MiCommand {
+        MiCommand {
+            operation: "exec-run",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    // Like exec_run, but stops at the beginning of the main procedure.
+    pub fn exec_start() -> MiCommand {
+        MiCommand {
+            operation: "exec-run",
+            options: vec![OsString::from("--start")],
+            parameters: Vec::new(),
+        }
+    }
+
+    fn exec_resume(operation: &'static str, reverse: bool) -> MiCommand {
+        MiCommand {
+            operation: operation,
+            options: if reverse {
+                vec![OsString::from("--reverse")]
+            } else {
+                vec![]
+            },
+            parameters: Vec::new(),
+        }
+    }
+
+    // Reverse execution requires the execution of the inferior to be recorded (or replayed
+    // by a target like rr).
+    pub fn exec_next(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-next", reverse)
+    }
+
+    pub fn exec_next_instruction(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-next-instruction", reverse)
+    }
+
+    pub fn exec_step(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-step", reverse)
+    }
+
+    pub fn exec_step_instruction(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-step-instruction", reverse)
+    }
+
+    pub fn exec_continue(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-continue", reverse)
+    }
+
+    pub fn exec_finish(reverse: bool) -> MiCommand {
+        Self::exec_resume("exec-finish", reverse)
+    }
+
     pub fn exit() -> MiCommand {
         MiCommand {
             operation: "gdb-exit",
@@ -243,6 +436,22 @@ impl MiCommand {
         }
     }
 
+    pub fn gdb_set(variable: &str, value: &str) -> MiCommand {
+        MiCommand {
+            operation: "gdb-set",
+            options: vec![OsString::from(variable), OsString::from(value)],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn gdb_show(variable: &str) -> MiCommand {
+        MiCommand {
+            operation: "gdb-show",
+            options: vec![OsString::from(variable)],
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn select_frame(frame_number: u64) -> MiCommand {
         MiCommand {
             operation: "stack-select-frame",
@@ -271,6 +480,60 @@ impl MiCommand {
         }
     }
 
+    // Frames from `low` to `high` (inclusive) or all frames.
+    pub fn stack_list_frames(range: Option<(u64, u64)>) -> MiCommand {
+        MiCommand {
+            operation: "stack-list-frames",
+            options: if let Some((low, high)) = range {
+                vec![low.to_string().into(), high.to_string().into()]
+            } else {
+                vec![]
+            },
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn stack_list_variables(print_mode: ValuePrintMode) -> MiCommand {
+        MiCommand {
+            operation: "stack-list-variables",
+            options: vec![(print_mode as u8).to_string().into()],
+            parameters: Vec::new(),
+        }
+    }
+
+    // Address is either host:port or a serial device.
+    pub fn target_select(target_type: TargetType, address: &OsStr) -> MiCommand {
+        MiCommand {
+            operation: "target-select",
+            options: vec![OsString::from(target_type.as_str()), address.to_owned()],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn target_attach(pid: u32) -> MiCommand {
+        MiCommand {
+            operation: "target-attach",
+            options: vec![OsString::from(pid.to_string())],
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn target_detach() -> MiCommand {
+        MiCommand {
+            operation: "target-detach",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
+    pub fn target_download() -> MiCommand {
+        MiCommand {
+            operation: "target-download",
+            options: Vec::new(),
+            parameters: Vec::new(),
+        }
+    }
+
     pub fn thread_info(thread_id: Option<u64>) -> MiCommand {
         MiCommand {
             operation: "thread-info",
@@ -45,6 +45,9 @@ pub struct GDBBuilder {
     opt_args: Vec<OsString>,
     opt_program: Option<PathBuf>,
     opt_tty: Option<PathBuf>,
+    opt_init_commands: Vec<OsString>,
+    opt_sysroot: Option<PathBuf>,
+    opt_solib_search_path: Option<OsString>,
 }
 impl GDBBuilder {
     pub fn new(gdb: PathBuf) -> Self {
@@ -63,6 +66,9 @@ impl GDBBuilder {
             opt_args: Vec::new(),
             opt_program: None,
             opt_tty: None,
+            opt_init_commands: Vec::new(),
+            opt_sysroot: None,
+            opt_solib_search_path: None,
         }
     }
 
@@ -118,6 +124,20 @@ impl GDBBuilder {
         self.opt_tty = Some(tty);
         self
     }
+    // Used to find the libraries of remote targets (and core files).
+    pub fn sysroot(mut self, sysroot: PathBuf) -> Self {
+        self.opt_sysroot = Some(sysroot);
+        self
+    }
+    pub fn solib_search_path(mut self, path: OsString) -> Self {
+        self.opt_solib_search_path = Some(path);
+        self
+    }
+    // Executed (in order) after loading the program, before any interaction.
+    pub fn init_command<S: Into<OsString>>(mut self, command: S) -> Self {
+        self.opt_init_commands.push(command.into());
+        self
+    }
     pub fn try_spawn<S>(self, oob_sink: S) -> Result<GDB, ::std::io::Error>
     where
         S: OutOfBandRecordSink + 'static,
@@ -160,10 +180,25 @@ impl GDBBuilder {
             args.push("--directory=".into());
             args.last_mut().unwrap().push(&source_dir);
         }
+        // These have to be set before loading the program (or core file).
+        if let Some(sysroot) = self.opt_sysroot {
+            args.push("--init-eval-command".into());
+            args.push("set sysroot ".into());
+            args.last_mut().unwrap().push(&sysroot);
+        }
+        if let Some(solib_search_path) = self.opt_solib_search_path {
+            args.push("--init-eval-command".into());
+            args.push("set solib-search-path ".into());
+            args.last_mut().unwrap().push(&solib_search_path);
+        }
         if let Some(tty) = self.opt_tty {
             args.push("--tty=".into());
             args.last_mut().unwrap().push(&tty);
         }
+        for command in self.opt_init_commands {
+            args.push("--eval-command".into());
+            args.push(command);
+        }
         if !self.opt_args.is_empty() {
             args.push("--args".into());
             args.push(self.opt_program.unwrap().into());
@@ -221,16 +256,28 @@ impl GDB {
     pub fn execute<C: std::borrow::Borrow<commands::MiCommand>>(
         &mut self,
         command: C,
+    ) -> Result<output::ResultRecord, ExecuteError> {
+        self.execute_with(|stdin, token| command.borrow().write_interpreter_string(stdin, token))
+    }
+
+    // Execute an mi command that is only available as a string (e.g., "-break-list" entered by a
+    // user). The command must not contain line breaks.
+    pub fn execute_raw(&mut self, command: &str) -> Result<output::ResultRecord, ExecuteError> {
+        use std::io::Write;
+        debug_assert!(!command.contains('\n'), "Line break in raw mi command");
+        self.execute_with(|stdin, token| writeln!(stdin, "{}{}", token, command))
+    }
+
+    fn execute_with<F: FnOnce(&mut ChildStdin, Token) -> std::io::Result<()>>(
+        &mut self,
+        write_command: F,
     ) -> Result<output::ResultRecord, ExecuteError> {
         if self.is_running() {
             return Err(ExecuteError::Busy);
         }
         let command_token = self.get_usable_token();
 
-        command
-            .borrow()
-            .write_interpreter_string(&mut self.stdin, command_token)
-            .expect("write interpreter command");
+        write_command(&mut self.stdin, command_token).expect("write interpreter command");
         loop {
             match self.result_output.recv() {
                 Ok(record) => match record.token {
@@ -34,6 +34,9 @@ pub enum AsyncClass {
     Stopped,
     CmdParamChanged,
     LibraryLoaded,
+    LibraryUnloaded,
+    RecordStarted,
+    RecordStopped,
     Thread(ThreadEvent),
     BreakPoint(BreakPointEvent),
     Other(String), //?
@@ -321,6 +324,9 @@ named!(
             )
             | value!(AsyncClass::CmdParamChanged, tag!("cmd-param-changed"))
             | value!(AsyncClass::LibraryLoaded, tag!("library-loaded"))
+            | value!(AsyncClass::LibraryUnloaded, tag!("library-unloaded"))
+            | value!(AsyncClass::RecordStarted, tag!("record-started"))
+            | value!(AsyncClass::RecordStopped, tag!("record-stopped"))
             | value!(
                 AsyncClass::BreakPoint(BreakPointEvent::Created),
                 tag!("breakpoint-created")
@@ -424,5 +430,8 @@ mod test {
     #[test]
     fn test_output() {
         let _ = Output::parse("=library-loaded,ranges=[{}]\n");
+        let _ = Output::parse("=library-unloaded,id=\"/lib/libfoo.so\"\n");
+        let _ = Output::parse("=record-started,thread-group=\"i1\",method=\"full\"\n");
+        let _ = Output::parse("=record-stopped,thread-group=\"i1\"\n");
     }
 }
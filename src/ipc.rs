@@ -3,231 +3,1074 @@ use unix_socket::{UnixListener, UnixStream};
 use chan;
 use json;
 
-use gdb::BreakpointOperationError;
-use gdbmi::commands::{BreakPointLocation, MiCommand};
+use annotations::{Annotation, Severity};
+
+use gdb::{BreakPoint, BreakPointSet, BreakpointOperationError, EvaluationError, GDB};
+use gdbmi::commands::{BreakPointLocation, BreakPointNumber, MiCommand};
+use gdbmi::output::{
+    AsyncClass, BreakPointEvent, OutOfBandRecord, ResultClass, StreamKind, ThreadEvent,
+};
 use gdbmi::ExecuteError;
-use std::ffi::OsString;
+use ipc_protocol::{
+    is_stale_socket, metadata_path, socket_dir, socket_path_of_metadata, try_read_ipc_message,
+    write_ipc_message, PROTOCOL_VERSION,
+};
+use log::{info, warn};
+use nix::errno::Errno;
+use nix::poll::{poll, EventFlags, PollFd};
+use nix::sys::socket::{getsockopt, send, sockopt::PeerCredentials, MsgFlags};
+use nix::unistd::getuid;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs;
-use std::io::Read;
-use std::io::Write;
+use std::io::{self, Write};
+use std::net::Shutdown;
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
 use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use unsegen::base::LineNumber;
+use UIRequest;
+
+// Allows clients to react to errors without having to parse the (human readable) reason.
+#[derive(Clone, Copy)]
+enum IPCErrorKind {
+    MalformedRequest,
+    UnknownFunction,
+    InvalidParameters,
+    NotFound,
+    UnsupportedVersion,
+    Busy,
+    GDBQuit,
+    GDBError,
+}
+
+impl IPCErrorKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            IPCErrorKind::MalformedRequest => "malformed_request",
+            IPCErrorKind::UnknownFunction => "unknown_function",
+            IPCErrorKind::InvalidParameters => "invalid_parameters",
+            IPCErrorKind::NotFound => "not_found",
+            IPCErrorKind::UnsupportedVersion => "unsupported_version",
+            IPCErrorKind::Busy => "busy",
+            IPCErrorKind::GDBQuit => "gdb_quit",
+            IPCErrorKind::GDBError => "gdb_error",
+        }
+    }
+}
 
 struct IPCError {
+    kind: IPCErrorKind,
     reason: &'static str,
     details: String,
 }
 impl IPCError {
-    fn new<S: Into<String>>(reason: &'static str, details: S) -> Self {
+    fn new<S: Into<String>>(kind: IPCErrorKind, reason: &'static str, details: S) -> Self {
         IPCError {
+            kind: kind,
             reason: reason,
             details: details.into(),
         }
     }
+    fn from_execute_error(reason: &'static str, error: ExecuteError) -> Self {
+        match error {
+            //TODO: we may want to investigate if we can interrupt execution, execute the
+            //command, and resume execution thereafter.
+            ExecuteError::Busy => IPCError::new(IPCErrorKind::Busy, reason, "GDB is busy"),
+            ExecuteError::Quit => IPCError::new(IPCErrorKind::GDBQuit, reason, "GDB quit"),
+        }
+    }
+    fn from_breakpoint_error(reason: &'static str, error: BreakpointOperationError) -> Self {
+        match error {
+            BreakpointOperationError::Busy => {
+                IPCError::new(IPCErrorKind::Busy, reason, "GDB is busy")
+            }
+            BreakpointOperationError::ExecutionError(msg) => {
+                IPCError::new(IPCErrorKind::GDBError, reason, msg)
+            }
+        }
+    }
     fn into_json(self) -> json::JsonValue {
         object! {
             "type" => "error",
+            "kind" => self.kind.as_str(),
             "reason" => self.reason,
             "details" => self.details
         }
     }
 }
 
-pub struct IPCRequest {
-    raw_request: Vec<u8>,
-    response_channel: UnixStream,
+fn get_str_parameter<'a>(
+    parameters: &'a json::JsonValue,
+    name: &str,
+    reason: &'static str,
+) -> Result<&'a str, IPCError> {
+    if !parameters.is_object() {
+        return Err(IPCError::new(
+            IPCErrorKind::InvalidParameters,
+            "Parameters is not an object",
+            parameters.dump(),
+        ));
+    }
+    parameters[name]
+        .as_str()
+        .ok_or_else(|| IPCError::new(IPCErrorKind::InvalidParameters, reason, parameters.dump()))
 }
 
-impl IPCRequest {
-    pub fn respond(mut self, p: ::UpdateParameters) {
-        let reply = match Self::handle(p, self.raw_request) {
-            Ok(reply_success) => reply_success,
-            Err(reply_fail) => reply_fail.into_json(),
-        };
-        // Client may just close the channel, so we ignore any errors.
-        // If they mess up it's on them.
-        let _ = write_ipc_response(&mut self.response_channel, reply.dump().as_bytes());
+fn get_file_and_line_parameters(parameters: &json::JsonValue) -> Result<(&str, u32), IPCError> {
+    let file = get_str_parameter(parameters, "file", "Missing file name")?;
+    if file.is_empty() {
+        return Err(IPCError::new(
+            IPCErrorKind::InvalidParameters,
+            "Missing file name",
+            parameters.dump(),
+        ));
     }
+    let line = parameters["line"].as_u32().ok_or_else(|| {
+        IPCError::new(
+            IPCErrorKind::InvalidParameters,
+            "Missing integer line number",
+            parameters.dump(),
+        )
+    })?;
+    Ok((file, line))
+}
 
-    fn handle(p: ::UpdateParameters, raw_request: Vec<u8>) -> Result<json::JsonValue, IPCError> {
-        let str_request = ::std::str::from_utf8(raw_request.as_slice())
-            .map_err(|_| IPCError::new("Malformed utf8.", ""))?;
-        let json_request =
-            json::parse(str_request).map_err(|_| IPCError::new("Malformed json.", str_request))?;
+fn get_annotation(value: &json::JsonValue) -> Result<Annotation, IPCError> {
+    let (file, line) = get_file_and_line_parameters(value)?;
+    if line == 0 {
+        return Err(IPCError::new(
+            IPCErrorKind::InvalidParameters,
+            "Line numbers start at 1",
+            value.dump(),
+        ));
+    }
+    let severity = match value["severity"] {
+        json::JsonValue::Null => Severity::Info,
+        ref severity => severity
+            .as_str()
+            .and_then(Severity::from_str)
+            .ok_or_else(|| {
+                IPCError::new(
+                    IPCErrorKind::InvalidParameters,
+                    "Severity is not one of 'info', 'warning' or 'error'",
+                    severity.dump(),
+                )
+            })?,
+    };
+    Ok(Annotation {
+        file: PathBuf::from(file),
+        line: LineNumber::new(line as usize),
+        severity: severity,
+        text: value["text"].as_str().unwrap_or("").replace('\n', " "),
+    })
+}
 
-        let (function_name, parameters) = match json_request {
-            json::JsonValue::Object(ref obj) => {
-                let function_name = obj
-                    .get("function")
-                    .and_then(|o| o.as_str())
-                    .ok_or(IPCError::new("Missing function name", json_request.dump()))?;
+fn get_annotations_parameter(parameters: &json::JsonValue) -> Result<Vec<Annotation>, IPCError> {
+    if let json::JsonValue::Array(ref annotations) = parameters["annotations"] {
+        annotations.iter().map(get_annotation).collect()
+    } else {
+        Err(IPCError::new(
+            IPCErrorKind::InvalidParameters,
+            "Annotations is not a list",
+            parameters["annotations"].dump(),
+        ))
+    }
+}
 
-                let parameters = &obj["parameters"];
+fn result_class_name(class: ResultClass) -> &'static str {
+    match class {
+        ResultClass::Done => "done",
+        ResultClass::Running => "running",
+        ResultClass::Connected => "connected",
+        ResultClass::Error => "error",
+        ResultClass::Exit => "exit",
+    }
+}
+
+fn breakpoint_to_json(bp: &BreakPoint) -> json::JsonValue {
+    let mut obj = object! {
+        "number" => bp.number.to_string(),
+        "enabled" => bp.enabled,
+        "address" => json::JsonValue::Null,
+        "file" => json::JsonValue::Null,
+        "line" => json::JsonValue::Null
+    };
+    if let Some(address) = bp.address {
+        obj["address"] = address.to_string().into();
+    }
+    if let Some(ref pos) = bp.src_pos {
+        let line: usize = pos.line.into();
+        obj["file"] = pos.file.to_string_lossy().into_owned().into();
+        obj["line"] = line.into();
+    }
+    obj
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum EventTopic {
+    Stopped,
+    Running,
+    Breakpoint,
+    Thread,
+    Exited,
+    Console,
+}
+
+impl EventTopic {
+    const ALL: [EventTopic; 6] = [
+        EventTopic::Stopped,
+        EventTopic::Running,
+        EventTopic::Breakpoint,
+        EventTopic::Thread,
+        EventTopic::Exited,
+        EventTopic::Console,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            EventTopic::Stopped => "stopped",
+            EventTopic::Running => "running",
+            EventTopic::Breakpoint => "breakpoint",
+            EventTopic::Thread => "thread",
+            EventTopic::Exited => "exited",
+            EventTopic::Console => "console",
+        }
+    }
+
+    fn from_str(topic: &str) -> Option<Self> {
+        Self::ALL.iter().cloned().find(|t| t.as_str() == topic)
+    }
+}
 
-                (function_name, parameters)
+struct Subscriber {
+    connection: UnixStream,
+    topics: HashSet<EventTopic>,
+}
+
+// Writes to the connection of a subscriber without blocking for more than WRITE_TIMEOUT_MS per
+// message. Socket options like a write timeout would also apply to responses (which are written to
+// the same socket and may take longer to be read), so we poll instead.
+struct EventWriter<'a> {
+    connection: &'a UnixStream,
+    deadline: Instant,
+}
+
+impl<'a> EventWriter<'a> {
+    fn new(connection: &'a UnixStream) -> Self {
+        EventWriter {
+            connection: connection,
+            deadline: Instant::now() + Duration::from_millis(WRITE_TIMEOUT_MS),
+        }
+    }
+}
+
+impl<'a> Write for EventWriter<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let fd = self.connection.as_raw_fd();
+        loop {
+            match send(fd, buf, MsgFlags::MSG_DONTWAIT) {
+                Ok(written) => return Ok(written),
+                Err(::nix::Error::Sys(Errno::EAGAIN)) | Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => return Err(to_io_error(e)),
             }
-            _ => {
-                return Err(IPCError::new(
-                    "Malformed (non-object) request",
-                    json_request.dump(),
+            let now = Instant::now();
+            if now >= self.deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    "Subscriber does not read events",
                 ));
             }
-        };
-        let result = Self::dispatch(function_name)?(p, parameters)?;
+            let remaining = self.deadline - now;
+            let timeout_ms =
+                remaining.as_secs() as i32 * 1000 + remaining.subsec_millis() as i32 + 1;
+            match poll(&mut [PollFd::new(fd, EventFlags::POLLOUT)], timeout_ms) {
+                Ok(_) | Err(::nix::Error::Sys(Errno::EINTR)) => {}
+                Err(e) => return Err(to_io_error(e)),
+            }
+        }
+    }
 
-        Ok(object! {
-            "type" => "success",
-            "result" => result
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn to_io_error(e: ::nix::Error) -> io::Error {
+    match e {
+        ::nix::Error::Sys(errno) => io::Error::from_raw_os_error(errno as i32),
+        e => io::Error::new(io::ErrorKind::Other, e.to_string()),
+    }
+}
+
+// Clients that subscribed to events receive them as messages (with the same framing as responses)
+// on the connection that they used to subscribe.
+pub struct EventSubscriptions {
+    subscribers: HashMap<ConnectionId, Subscriber>,
+}
+
+impl EventSubscriptions {
+    pub fn new() -> Self {
+        EventSubscriptions {
+            subscribers: HashMap::new(),
+        }
+    }
+
+    fn subscribed_topics(&self, connection_id: ConnectionId) -> json::JsonValue {
+        let mut topics = self
+            .subscribers
+            .get(&connection_id)
+            .map(|s| s.topics.iter().map(|t| t.as_str()).collect::<Vec<_>>())
+            .unwrap_or_default();
+        topics.sort();
+        object! {
+            "topics" => topics
+        }
+    }
+
+    fn publish(&mut self, topic: EventTopic, event: json::JsonValue) {
+        if !self.subscribers.values().any(|s| s.topics.contains(&topic)) {
+            return;
+        }
+        let msg = object! {
+            "type" => "event",
+            "topic" => topic.as_str(),
+            "event" => event
+        }
+        .dump();
+        // Clients that do not read their events (in time) are dropped, so that ugdb does not block.
+        // Their connection is closed as well, since it may end with a partially written message.
+        self.subscribers.retain(|_, subscriber| {
+            if !subscriber.topics.contains(&topic) {
+                return true;
+            }
+            let mut writer = EventWriter::new(&subscriber.connection);
+            if write_ipc_message(&mut writer, msg.as_bytes()).is_ok() {
+                true
+            } else {
+                let _ = subscriber.connection.shutdown(Shutdown::Both);
+                false
+            }
+        });
+    }
+
+    pub fn handle_out_of_band_record(&mut self, record: &OutOfBandRecord) {
+        if self.subscribers.is_empty() {
+            return;
+        }
+        match record {
+            &OutOfBandRecord::StreamRecord { ref kind, ref data } => {
+                let stream = match kind {
+                    &StreamKind::Console => "console",
+                    &StreamKind::Target => "target",
+                    &StreamKind::Log => "log",
+                };
+                self.publish(
+                    EventTopic::Console,
+                    object! {
+                        "stream" => stream,
+                        "text" => data.as_str()
+                    },
+                );
+            }
+            &OutOfBandRecord::AsyncRecord {
+                ref class,
+                ref results,
+                ..
+            } => {
+                let mut event = json::JsonValue::Object(results.clone());
+                let topic = match class {
+                    &AsyncClass::Stopped => EventTopic::Stopped,
+                    &AsyncClass::Other(ref class) if class == "running" => EventTopic::Running,
+                    &AsyncClass::Thread(ThreadEvent::GroupExited) => EventTopic::Exited,
+                    &AsyncClass::Thread(thread_event) => {
+                        event["event"] = match thread_event {
+                            ThreadEvent::Created => "created",
+                            ThreadEvent::GroupStarted => "group-started",
+                            ThreadEvent::Exited => "exited",
+                            ThreadEvent::GroupExited => "group-exited",
+                            ThreadEvent::Selected => "selected",
+                        }
+                        .into();
+                        EventTopic::Thread
+                    }
+                    // Breakpoint changes (including the ones that gdb does not notify us about)
+                    // are published from publish_breakpoint_changes.
+                    _ => return,
+                };
+                self.publish(topic, event);
+            }
+        }
+    }
+
+    pub fn publish_breakpoint_changes(&mut self, breakpoints: &mut BreakPointSet) {
+        for (event, number) in breakpoints.drain_changes() {
+            let mut breakpoint = match (event, breakpoints.get(&number)) {
+                (BreakPointEvent::Deleted, _) | (_, None) => object! {
+                    "number" => number.to_string()
+                },
+                (_, Some(bp)) => breakpoint_to_json(bp),
+            };
+            breakpoint["event"] = match event {
+                BreakPointEvent::Created => "created",
+                BreakPointEvent::Modified => "modified",
+                BreakPointEvent::Deleted => "deleted",
+            }
+            .into();
+            self.publish(EventTopic::Breakpoint, breakpoint);
+        }
+    }
+}
+
+type IPCFunction =
+    fn(::UpdateParameters, &json::JsonValue, &IPCConnection) -> Result<json::JsonValue, IPCError>;
+
+// All functions that can be called by clients. Keep in sync with doc/ipc-schema.json.
+const FUNCTIONS: &'static [(&'static str, IPCFunction)] = &[
+    ("get_capabilities", IPCRequest::get_capabilities),
+    ("get_instance_info", IPCRequest::get_instance_info),
+    ("set_breakpoint", IPCRequest::set_breakpoint),
+    ("remove_breakpoint", IPCRequest::remove_breakpoint),
+    ("toggle_breakpoint", IPCRequest::toggle_breakpoint),
+    ("list_breakpoints", IPCRequest::list_breakpoints),
+    ("evaluate_expression", IPCRequest::evaluate_expression),
+    ("get_current_location", IPCRequest::get_current_location),
+    ("execute_command", IPCRequest::execute_command),
+    ("add_watch_expression", IPCRequest::add_watch_expression),
+    ("show_location", IPCRequest::show_location),
+    ("show_message", IPCRequest::show_message),
+    ("set_annotations", IPCRequest::set_annotations),
+    ("add_annotations", IPCRequest::add_annotations),
+    ("clear_annotations", IPCRequest::clear_annotations),
+    ("list_annotations", IPCRequest::list_annotations),
+    ("subscribe", IPCRequest::subscribe),
+    ("unsubscribe", IPCRequest::unsubscribe),
+];
+
+fn dispatch(function_name: &str) -> Result<IPCFunction, IPCError> {
+    FUNCTIONS
+        .iter()
+        .find(|&&(name, _)| name == function_name)
+        .map(|&(_, function)| function)
+        .ok_or_else(|| {
+            IPCError::new(
+                IPCErrorKind::UnknownFunction,
+                "unknown function",
+                function_name,
+            )
         })
+}
+
+struct Request {
+    id: json::JsonValue,
+    function: String,
+    parameters: json::JsonValue,
+}
+
+// The id of the request is returned along with the error (if it could be parsed), so that clients
+// can still match the error response to their request.
+fn parse_request(raw_request: &[u8]) -> Result<Request, (json::JsonValue, IPCError)> {
+    let str_request = ::std::str::from_utf8(raw_request).map_err(|_| {
+        (
+            json::JsonValue::Null,
+            IPCError::new(IPCErrorKind::MalformedRequest, "Malformed utf8.", ""),
+        )
+    })?;
+    let json_request = json::parse(str_request).map_err(|_| {
+        (
+            json::JsonValue::Null,
+            IPCError::new(
+                IPCErrorKind::MalformedRequest,
+                "Malformed json.",
+                str_request,
+            ),
+        )
+    })?;
+    if !json_request.is_object() {
+        return Err((
+            json::JsonValue::Null,
+            IPCError::new(
+                IPCErrorKind::MalformedRequest,
+                "Malformed (non-object) request",
+                json_request.dump(),
+            ),
+        ));
+    }
+    let id = json_request["id"].clone();
+    match json_request["protocol_version"] {
+        json::JsonValue::Null => {}
+        ref version if version.as_u32() == Some(PROTOCOL_VERSION) => {}
+        ref version => {
+            return Err((
+                id,
+                IPCError::new(
+                    IPCErrorKind::UnsupportedVersion,
+                    "Unsupported protocol version",
+                    format!(
+                        "Requested version {}, but ugdb speaks version {}",
+                        version.dump(),
+                        PROTOCOL_VERSION
+                    ),
+                ),
+            ));
+        }
     }
+    let function = if let Some(function) = json_request["function"].as_str() {
+        function.to_owned()
+    } else {
+        return Err((
+            id,
+            IPCError::new(
+                IPCErrorKind::MalformedRequest,
+                "Missing function name",
+                json_request.dump(),
+            ),
+        ));
+    };
+    Ok(Request {
+        id: id,
+        function: function,
+        parameters: json_request["parameters"].clone(),
+    })
+}
+
+type ConnectionId = usize;
 
-    fn dispatch(
-        function_name: &str,
-    ) -> Result<
-        fn(p: ::UpdateParameters, &json::JsonValue) -> Result<json::JsonValue, IPCError>,
-        IPCError,
-    > {
-        match function_name {
-            "set_breakpoint" => Ok(Self::set_breakpoint),
-            "get_instance_info" => Ok(Self::get_instance_info),
-            _ => Err(IPCError::new("unknown function", function_name)),
+struct IPCConnection {
+    id: ConnectionId,
+    stream: UnixStream,
+}
+
+pub struct IPCRequest {
+    // None if the connection has been closed, so that its event subscriptions can be removed.
+    raw_request: Option<Vec<u8>>,
+    connection: IPCConnection,
+}
+
+impl IPCRequest {
+    pub fn respond(mut self, p: ::UpdateParameters) {
+        let raw_request = if let Some(raw_request) = self.raw_request.take() {
+            raw_request
+        } else {
+            p.event_subscriptions
+                .subscribers
+                .remove(&self.connection.id);
+            return;
+        };
+        let (id, reply) = match parse_request(&raw_request) {
+            Ok(request) => {
+                let result = dispatch(&request.function)
+                    .and_then(|function| function(p, &request.parameters, &self.connection));
+                (request.id, result)
+            }
+            Err((id, e)) => (id, Err(e)),
+        };
+        let mut reply = match reply {
+            Ok(result) => object! {
+                "type" => "success",
+                "result" => result
+            },
+            Err(e) => e.into_json(),
+        };
+        if !id.is_null() {
+            reply["id"] = id;
+        }
+        // Client may just close the channel, so we ignore any errors.
+        // If they mess up it's on them. (But they must not receive the rest of a partially written
+        // message as the next one.)
+        if write_ipc_message(&mut self.connection.stream, reply.dump().as_bytes()).is_err() {
+            let _ = self.connection.stream.shutdown(Shutdown::Both);
         }
     }
 
     fn set_breakpoint(
         p: ::UpdateParameters,
         parameters: &json::JsonValue,
+        _: &IPCConnection,
     ) -> Result<json::JsonValue, IPCError> {
-        let parameters_obj = if let &json::JsonValue::Object(ref parameters_obj) = parameters {
-            parameters_obj
-        } else {
+        let (file, line) = get_file_and_line_parameters(parameters)?;
+        p.gdb
+            .insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
+            .map_err(|e| IPCError::from_breakpoint_error("Could not insert breakpoint", e))?;
+        Ok(json::JsonValue::String(format!(
+            "Inserted breakpoint at {}:{}",
+            file, line
+        )))
+    }
+
+    // The file of the breakpoint only has to end with the given path, so that clients do not have
+    // to know the full path that gdb uses.
+    fn breakpoints_at(p: ::UpdateParameters, file: &str, line: u32) -> Vec<BreakPointNumber> {
+        p.gdb
+            .breakpoints
+            .values()
+            .filter(|bp| {
+                bp.src_pos
+                    .as_ref()
+                    .map(|pos| {
+                        let bp_line: usize = pos.line.into();
+                        pos.file.ends_with(file) && bp_line == line as usize
+                    })
+                    .unwrap_or(false)
+            })
+            .map(|bp| bp.number)
+            .collect()
+    }
+
+    fn remove_breakpoint(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let (file, line) = get_file_and_line_parameters(parameters)?;
+        let numbers = Self::breakpoints_at(p, file, line);
+        if numbers.is_empty() {
             return Err(IPCError::new(
-                "Parameters is not an object",
-                parameters.dump(),
+                IPCErrorKind::NotFound,
+                "Could not remove breakpoint",
+                format!("No breakpoint at {}:{}", file, line),
             ));
+        }
+        p.gdb
+            .delete_breakpoints(numbers.into_iter())
+            .map_err(|e| IPCError::from_breakpoint_error("Could not remove breakpoint", e))?;
+        Ok(json::JsonValue::String(format!(
+            "Removed breakpoint at {}:{}",
+            file, line
+        )))
+    }
+
+    fn toggle_breakpoint(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let (file, line) = get_file_and_line_parameters(parameters)?;
+        let numbers = Self::breakpoints_at(p, file, line);
+        let action = if numbers.is_empty() {
+            p.gdb
+                .insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
+                .map_err(|e| IPCError::from_breakpoint_error("Could not insert breakpoint", e))?;
+            "inserted"
+        } else {
+            p.gdb
+                .delete_breakpoints(numbers.into_iter())
+                .map_err(|e| IPCError::from_breakpoint_error("Could not remove breakpoint", e))?;
+            "removed"
         };
-        let file = parameters_obj
-            .get("file")
-            .and_then(|o| o.as_str())
-            .ok_or(IPCError::new("Missing file name", parameters.dump()))?;
-        let line = parameters_obj
-            .get("line")
-            .and_then(|o| o.as_u32())
-            .ok_or(IPCError::new(
-                "Missing integer line number",
-                parameters.dump(),
-            ))?;
-        match p
+        Ok(object! {
+            "action" => action
+        })
+    }
+
+    fn list_breakpoints(
+        p: ::UpdateParameters,
+        _: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let mut breakpoints = p.gdb.breakpoints.values().collect::<Vec<_>>();
+        breakpoints.sort_by_key(|bp| (bp.number.major, bp.number.minor));
+        Ok(json::JsonValue::Array(
+            breakpoints.into_iter().map(breakpoint_to_json).collect(),
+        ))
+    }
+
+    fn evaluate_expression(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let expression = get_str_parameter(parameters, "expression", "Missing expression")?;
+        p.gdb
+            .evaluate_expression(expression.to_owned())
+            .map_err(|e| match e {
+                EvaluationError::Execution(e) => {
+                    IPCError::from_execute_error("Could not evaluate expression", e)
+                }
+                EvaluationError::Gdb(msg) => {
+                    IPCError::new(IPCErrorKind::GDBError, "Could not evaluate expression", msg)
+                }
+            })
+    }
+
+    fn get_current_location(
+        p: ::UpdateParameters,
+        _: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let result = p
             .gdb
-            .insert_breakpoint(BreakPointLocation::Line(Path::new(file), line as usize))
-        {
-            Ok(()) => Ok(json::JsonValue::String(format!(
-                "Inserted breakpoint at {}:{}",
-                file, line
-            ))),
-            Err(BreakpointOperationError::Busy) => {
-                //TODO: we may want to investigate if we can interrupt execution, insert
-                //breakpoint, and resume execution thereafter.
-                Err(IPCError::new("Could not insert breakpoint", "GDB is busy"))
+            .mi
+            .execute(MiCommand::stack_info_frame(None))
+            .map_err(|e| IPCError::from_execute_error("Could not get current location", e))?;
+        if result.class == ResultClass::Error {
+            return Err(IPCError::new(
+                IPCErrorKind::GDBError,
+                "Could not get current location",
+                result.results["msg"].as_str().unwrap_or("Unknown error"),
+            ));
+        }
+        let frame = &result.results["frame"];
+        let parse_number = |field: &str| {
+            frame[field]
+                .as_str()
+                .and_then(|s| s.parse::<u64>().ok())
+                .map(json::JsonValue::from)
+                .unwrap_or(json::JsonValue::Null)
+        };
+        Ok(object! {
+            "file" => frame["fullname"].clone(),
+            "line" => parse_number("line"),
+            "address" => frame["addr"].clone(),
+            "function" => frame["func"].clone(),
+            "frame" => parse_number("level")
+        })
+    }
+
+    fn execute_command(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let command = get_str_parameter(parameters, "command", "Missing command")?;
+        if command.contains('\n') {
+            return Err(IPCError::new(
+                IPCErrorKind::InvalidParameters,
+                "Command contains a line break",
+                command,
+            ));
+        }
+        let result = match parameters["type"].as_str().unwrap_or("cli") {
+            "cli" => p.gdb.mi.execute(MiCommand::cli_exec(command)),
+            "mi" if command.starts_with('-') => p.gdb.mi.execute_raw(command),
+            "mi" => {
+                return Err(IPCError::new(
+                    IPCErrorKind::InvalidParameters,
+                    "MI commands have to start with '-'",
+                    command,
+                ));
             }
-            Err(BreakpointOperationError::ExecutionError(msg)) => {
-                //TODO: we may want to investigate if we can interrupt execution, insert
-                //breakpoint, and resume execution thereafter.
-                Err(IPCError::new("Could not insert breakpoint:", msg))
+            other => {
+                return Err(IPCError::new(
+                    IPCErrorKind::InvalidParameters,
+                    "Command type is neither 'cli' nor 'mi'",
+                    other,
+                ));
             }
         }
+        .map_err(|e| IPCError::from_execute_error("Could not execute command", e))?;
+        if result.class == ResultClass::Error {
+            return Err(IPCError::new(
+                IPCErrorKind::GDBError,
+                "Could not execute command",
+                result.results["msg"].as_str().unwrap_or("Unknown error"),
+            ));
+        }
+        // The output of cli commands is shown in the console (and not part of the result).
+        Ok(object! {
+            "class" => result_class_name(result.class),
+            "results" => json::JsonValue::Object(result.results)
+        })
     }
 
-    fn get_instance_info(
+    fn show_location(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let (file, line) = get_file_and_line_parameters(parameters)?;
+        if line == 0 {
+            return Err(IPCError::new(
+                IPCErrorKind::InvalidParameters,
+                "Line numbers start at 1",
+                parameters.dump(),
+            ));
+        }
+        let path = PathBuf::from(file);
+        if !path.is_file() {
+            return Err(IPCError::new(
+                IPCErrorKind::NotFound,
+                "Could not show location",
+                format!("No such file: {}", file),
+            ));
+        }
+        p.ui_request_sink.send(UIRequest::ShowLocation(
+            path,
+            LineNumber::new(line as usize),
+        ));
+        Ok(json::JsonValue::String(format!(
+            "Showing {}:{}",
+            file, line
+        )))
+    }
+
+    fn show_message(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let text = get_str_parameter(parameters, "text", "Missing message text")?;
+        p.message_sink.send(text.to_owned());
+        Ok(json::JsonValue::String("Message shown".to_owned()))
+    }
+
+    fn add_watch_expression(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let expression = get_str_parameter(parameters, "expression", "Missing expression")?;
+        p.ui_request_sink
+            .send(UIRequest::AddWatchExpression(expression.to_owned()));
+        Ok(json::JsonValue::String(format!(
+            "Added watch expression {}",
+            expression
+        )))
+    }
+
+    fn set_annotations(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let name = get_str_parameter(parameters, "set", "Missing annotation set name")?;
+        let annotations = get_annotations_parameter(parameters)?;
+        let count = annotations.len();
+        p.annotations.set(name.to_owned(), annotations);
+        Ok(object! {
+            "set" => name,
+            "annotations" => count
+        })
+    }
+
+    // Like set_annotations, but keeps the annotations that the set already contains.
+    fn add_annotations(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let name = get_str_parameter(parameters, "set", "Missing annotation set name")?;
+        let annotations = get_annotations_parameter(parameters)?;
+        let count = p.annotations.add(name.to_owned(), annotations);
+        Ok(object! {
+            "set" => name,
+            "annotations" => count
+        })
+    }
+
+    // Without a set name, all sets are cleared.
+    fn clear_annotations(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        if parameters["set"].is_null() {
+            p.annotations.clear_all();
+            return Ok(json::JsonValue::String(
+                "Cleared all annotation sets".to_owned(),
+            ));
+        }
+        let name = get_str_parameter(parameters, "set", "Annotation set name is not a string")?;
+        if p.annotations.clear(name) {
+            Ok(json::JsonValue::String(format!(
+                "Cleared annotation set {}",
+                name
+            )))
+        } else {
+            Err(IPCError::new(
+                IPCErrorKind::NotFound,
+                "Could not clear annotation set",
+                format!("No annotation set named {}", name),
+            ))
+        }
+    }
+
+    fn list_annotations(
         p: ::UpdateParameters,
         _: &json::JsonValue,
+        _: &IPCConnection,
     ) -> Result<json::JsonValue, IPCError> {
-        let result = p
-            .gdb
-            .mi
-            .execute(MiCommand::environment_pwd())
-            .map_err(|e| match e {
-                ExecuteError::Busy => {
-                    //TODO: we may want to investigate if we can interrupt execution, get information
-                    //and resume execution thereafter.
-                    IPCError::new("Could not get working directory", "GDB is busy")
-                }
-                ExecuteError::Quit => IPCError::new("Could not get working directory", "GDB quit"),
+        Ok(json::JsonValue::Array(
+            p.annotations
+                .sets()
+                .map(|(name, annotations)| {
+                    object! {
+                        "set" => name.as_str(),
+                        "annotations" => annotations.len()
+                    }
+                })
+                .collect(),
+        ))
+    }
+
+    // Topics are given as a list of names. Without a list, all topics are (un)subscribed.
+    fn get_topics_parameter(parameters: &json::JsonValue) -> Result<Vec<EventTopic>, IPCError> {
+        match parameters["topics"] {
+            json::JsonValue::Null => Ok(EventTopic::ALL.to_vec()),
+            json::JsonValue::Array(ref topics) => topics
+                .iter()
+                .map(|topic| {
+                    topic
+                        .as_str()
+                        .and_then(EventTopic::from_str)
+                        .ok_or_else(|| {
+                            IPCError::new(
+                                IPCErrorKind::InvalidParameters,
+                                "Unknown topic",
+                                topic.dump(),
+                            )
+                        })
+                })
+                .collect(),
+            ref other => Err(IPCError::new(
+                IPCErrorKind::InvalidParameters,
+                "Topics is not a list",
+                other.dump(),
+            )),
+        }
+    }
+
+    fn subscribe(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        connection: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let topics = Self::get_topics_parameter(parameters)?;
+        if !p
+            .event_subscriptions
+            .subscribers
+            .contains_key(&connection.id)
+        {
+            let stream = connection.stream.try_clone().map_err(|e| {
+                IPCError::new(IPCErrorKind::GDBError, "Could not subscribe", e.to_string())
             })?;
-        let working_directory = result.results["cwd"].as_str().ok_or_else(|| {
-            IPCError::new("Could not get working directory", "Malformed GDB response")
-        })?;
+            p.event_subscriptions.subscribers.insert(
+                connection.id,
+                Subscriber {
+                    connection: stream,
+                    topics: HashSet::new(),
+                },
+            );
+        }
+        let subscriber = p
+            .event_subscriptions
+            .subscribers
+            .get_mut(&connection.id)
+            .expect("subscriber exists");
+        subscriber.topics.extend(topics);
+        Ok(p.event_subscriptions.subscribed_topics(connection.id))
+    }
+
+    fn unsubscribe(
+        p: ::UpdateParameters,
+        parameters: &json::JsonValue,
+        connection: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        let topics = Self::get_topics_parameter(parameters)?;
+        let now_empty =
+            if let Some(subscriber) = p.event_subscriptions.subscribers.get_mut(&connection.id) {
+                for topic in topics {
+                    subscriber.topics.remove(&topic);
+                }
+                subscriber.topics.is_empty()
+            } else {
+                false
+            };
+        if now_empty {
+            p.event_subscriptions.subscribers.remove(&connection.id);
+        }
+        Ok(p.event_subscriptions.subscribed_topics(connection.id))
+    }
+
+    fn get_capabilities(
+        _: ::UpdateParameters,
+        _: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
         Ok(object! {
-            "working_directory" => working_directory
+            "protocol_version" => PROTOCOL_VERSION,
+            "functions" => FUNCTIONS.iter().map(|&(name, _)| name).collect::<Vec<_>>(),
+            "event_topics" => EventTopic::ALL.iter().map(|t| t.as_str()).collect::<Vec<_>>()
         })
     }
+
+    fn get_instance_info(
+        p: ::UpdateParameters,
+        _: &json::JsonValue,
+        _: &IPCConnection,
+    ) -> Result<json::JsonValue, IPCError> {
+        query_instance_info(&mut p.gdb)
+    }
+}
+
+fn query_instance_info(gdb: &mut GDB) -> Result<json::JsonValue, IPCError> {
+    let result = gdb
+        .mi
+        .execute(MiCommand::environment_pwd())
+        .map_err(|e| IPCError::from_execute_error("Could not get working directory", e))?;
+    let working_directory = result.results["cwd"].as_str().ok_or_else(|| {
+        IPCError::new(
+            IPCErrorKind::GDBError,
+            "Could not get working directory",
+            "Malformed GDB response",
+        )
+    })?;
+    let target = gdb
+        .get_target()
+        .map_err(|e| IPCError::from_execute_error("Could not get target", e))?;
+    Ok(object! {
+        "working_directory" => working_directory,
+        "pid" => ::std::process::id(),
+        "target" => target.map(|t| t.to_string_lossy().into_owned())
+    })
 }
 
-const FALLBACK_RUNTIME_DIR: &'static str = "/tmp/";
-const RUNTIME_SUBDIR: &'static str = "ugdb";
 const SOCKET_IDENTIFIER_LENGTH: usize = 64;
-const IPC_MSG_IDENTIFIER: &'static [u8] = b"ugdb-ipc";
-const HEADER_LENGTH: usize = 12;
+// Writing events must not block ugdb if a client does not read them.
+const WRITE_TIMEOUT_MS: u64 = 100;
 
 pub struct IPC {
     socket_path: PathBuf,
+    start_time: u64,
     pub requests: chan::Receiver<IPCRequest>,
 }
 
-fn write_ipc_header<W: Write>(w: &mut W, msg_len: u32) -> ::std::io::Result<()> {
-    let msg_len = msg_len.to_le();
-    let msg_len_buf = [
-        msg_len as u8,
-        (msg_len >> 8) as u8,
-        (msg_len >> 16) as u8,
-        (msg_len >> 24) as u8,
-    ];
-    w.write_all(IPC_MSG_IDENTIFIER)?;
-    w.write_all(&msg_len_buf)?;
-    Ok(())
-}
-
-fn write_ipc_response<W: Write>(w: &mut W, msg: &[u8]) -> ::std::io::Result<()> {
-    write_ipc_header(w, msg.len() as u32)?;
-    w.write_all(msg)?;
-    Ok(())
-}
-
-fn try_read_ipc_header<R: Read>(r: &mut R) -> Result<u32, ()> {
-    // The header has to look like this with four bytes (****) for the message length in little endian: "ugdb-ipc****"
-    let mut buf = vec![0u8; HEADER_LENGTH];
-    r.read_exact(&mut buf).map_err(|_| {})?;
-    if &buf[0..8] == IPC_MSG_IDENTIFIER {
-        let mut len = 0;
-        len += buf[8] as u32;
-        len += (buf[9] as u32) << 8;
-        len += (buf[10] as u32) << 16;
-        len += (buf[11] as u32) << 24;
-        Ok(u32::from_le(len))
-    } else {
-        Err(())
-    }
-}
-
-fn try_read_ipc_request(connection: &mut UnixStream) -> Result<IPCRequest, ()> {
-    let msg_len = try_read_ipc_header(connection)?;
-
-    let mut msg_buf = vec![0u8; msg_len as usize];
-    connection.read_exact(&mut msg_buf).map_err(|_| {})?;
+fn try_read_ipc_request(
+    connection: &mut UnixStream,
+    connection_id: ConnectionId,
+) -> Result<IPCRequest, ()> {
+    let msg_buf = try_read_ipc_message(connection)?;
+    let stream = connection.try_clone().map_err(|e| {
+        warn!("Unable to clone ipc connection handle: {}", e);
+    })?;
     Ok(IPCRequest {
-        raw_request: msg_buf,
-        response_channel: connection.try_clone().expect("clone handle"),
+        raw_request: Some(msg_buf),
+        connection: IPCConnection {
+            id: connection_id,
+            stream: stream,
+        },
     })
 }
 
-fn start_connection(mut connection: UnixStream, request_sink: chan::Sender<IPCRequest>) {
+fn start_connection(
+    mut connection: UnixStream,
+    connection_id: ConnectionId,
+    request_sink: chan::Sender<IPCRequest>,
+) {
     let _ = thread::Builder::new()
         .name("IPC Connection".to_owned())
         .spawn(move || {
-            connection.set_nonblocking(false).expect("set blocking");
+            // The connection is dropped if it cannot be configured, just like connections that
+            // send malformed requests.
+            if let Err(e) = connection.set_nonblocking(false) {
+                warn!("Unable to configure ipc connection: {}", e);
+                return;
+            }
 
             loop {
-                match try_read_ipc_request(&mut connection) {
+                match try_read_ipc_request(&mut connection, connection_id) {
                     Ok(request) => {
                         request_sink.send(request);
                     }
@@ -237,47 +1080,336 @@ fn start_connection(mut connection: UnixStream, request_sink: chan::Sender<IPCRe
                     }
                 }
             }
+            // The connection is closed (by the client or due to an error), so events must not be
+            // sent to it anymore.
+            let _ = connection.shutdown(Shutdown::Both);
+            request_sink.send(IPCRequest {
+                raw_request: None,
+                connection: IPCConnection {
+                    id: connection_id,
+                    stream: connection,
+                },
+            });
         });
 }
 
+#[derive(Debug)]
+pub enum IPCSetupError {
+    InvalidSocketName(String),
+    SocketInUse(PathBuf),
+    InsecureDirectory(PathBuf, &'static str),
+    Directory(PathBuf, io::Error),
+    Socket(PathBuf, io::Error),
+}
+
+impl fmt::Display for IPCSetupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &IPCSetupError::InvalidSocketName(ref name) => write!(
+                f,
+                "Invalid socket name '{}'. It must not contain '/' or end in '.json'.",
+                name
+            ),
+            &IPCSetupError::SocketInUse(ref path) => write!(
+                f,
+                "Socket {} is already used by another instance.",
+                path.to_string_lossy()
+            ),
+            &IPCSetupError::InsecureDirectory(ref path, reason) => write!(
+                f,
+                "Refusing to use socket directory {}: {}.",
+                path.to_string_lossy(),
+                reason
+            ),
+            &IPCSetupError::Directory(ref path, ref e) => write!(
+                f,
+                "Unable to create socket directory {}: {}",
+                path.to_string_lossy(),
+                e
+            ),
+            &IPCSetupError::Socket(ref path, ref e) => write!(
+                f,
+                "Unable to create socket {}: {}",
+                path.to_string_lossy(),
+                e
+            ),
+        }
+    }
+}
+
+// Anyone who can connect to the socket can execute arbitrary commands via gdb, so the socket
+// directory must only be accessible by the current user. This is especially important for the
+// fallback directory in /tmp.
+fn create_private_dir(dir: &Path) -> Result<(), IPCSetupError> {
+    let directory_error = |e| IPCSetupError::Directory(dir.to_owned(), e);
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(directory_error(e)),
+    }
+    let metadata = fs::symlink_metadata(dir).map_err(directory_error)?;
+    if !metadata.file_type().is_dir() {
+        return Err(IPCSetupError::InsecureDirectory(
+            dir.to_owned(),
+            "Not a directory",
+        ));
+    }
+    if metadata.uid() != getuid().as_raw() {
+        return Err(IPCSetupError::InsecureDirectory(
+            dir.to_owned(),
+            "Owned by another user",
+        ));
+    }
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700)).map_err(directory_error)?;
+    }
+    Ok(())
+}
+
+pub fn is_same_user(connection: &UnixStream) -> bool {
+    match getsockopt(connection.as_raw_fd(), PeerCredentials) {
+        Ok(credentials) => credentials.uid() == getuid().as_raw(),
+        Err(_) => false,
+    }
+}
+
+fn is_valid_socket_name(name: &str) -> bool {
+    !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.contains('/')
+        && socket_path_of_metadata(Path::new(name)).is_none()
+}
+
+// Remove sockets (and their metadata) of instances that did not shut down properly.
+fn remove_stale_sockets(dir: &Path) {
+    let entries = if let Ok(entries) = fs::read_dir(dir) {
+        entries
+    } else {
+        return;
+    };
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        let path = entry.path();
+        if let Some(socket_path) = socket_path_of_metadata(&path) {
+            if !socket_path.exists() {
+                let _ = fs::remove_file(&path);
+            }
+        } else if entry.file_type().map(|t| t.is_socket()).unwrap_or(false)
+            && is_stale_socket(&path)
+        {
+            info!("Removing stale ipc socket {}", path.to_string_lossy());
+            let _ = fs::remove_file(&path);
+            let _ = fs::remove_file(metadata_path(&path));
+        }
+    }
+}
+
 impl IPC {
-    pub fn setup() -> ::std::io::Result<Self> {
+    // Without a `socket_name` a random one is chosen.
+    pub fn setup(socket_name: Option<String>) -> Result<Self, IPCSetupError> {
         let (request_sink, request_source) = chan::async();
 
-        let runtime_dir =
-            ::std::env::var_os("XDG_RUNTIME_DIR").unwrap_or(OsString::from(FALLBACK_RUNTIME_DIR));
-        let ugdb_dir = Path::join(runtime_dir.as_ref(), RUNTIME_SUBDIR);
-        let _ = fs::create_dir(&ugdb_dir); //Ignore error if dir exists, we check if we can access it soon.
+        let ugdb_dir = socket_dir();
+        create_private_dir(&ugdb_dir)?;
+        remove_stale_sockets(&ugdb_dir);
 
-        use rand::Rng;
-        let socket_name = ::rand::thread_rng()
-            .gen_ascii_chars()
-            .take(SOCKET_IDENTIFIER_LENGTH)
-            .collect::<String>();
+        let socket_name = if let Some(socket_name) = socket_name {
+            if !is_valid_socket_name(&socket_name) {
+                return Err(IPCSetupError::InvalidSocketName(socket_name));
+            }
+            socket_name
+        } else {
+            use rand::Rng;
+            ::rand::thread_rng()
+                .gen_ascii_chars()
+                .take(SOCKET_IDENTIFIER_LENGTH)
+                .collect::<String>()
+        };
         let socket_path = ugdb_dir.join(socket_name);
 
-        let listener = UnixListener::bind(&socket_path)?;
+        let listener = UnixListener::bind(&socket_path).map_err(|e| {
+            if e.kind() == io::ErrorKind::AddrInUse {
+                IPCSetupError::SocketInUse(socket_path.clone())
+            } else {
+                IPCSetupError::Socket(socket_path.clone(), e)
+            }
+        })?;
+        if let Err(e) = fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600)) {
+            let _ = fs::remove_file(&socket_path);
+            return Err(IPCSetupError::Socket(socket_path, e));
+        }
 
         let _ = thread::Builder::new()
             .name("IPC Connection Listener".to_owned())
             .spawn(move || {
-                for connection in listener.incoming() {
+                for (connection_id, connection) in listener.incoming().enumerate() {
                     if let Ok(connection) = connection {
-                        start_connection(connection, request_sink.clone());
+                        if is_same_user(&connection) {
+                            start_connection(connection, connection_id, request_sink.clone());
+                        } else {
+                            warn!("Rejected ipc connection from another user");
+                        }
                     }
                 }
             });
 
+        let start_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
         Ok(IPC {
             socket_path: socket_path,
+            start_time: start_time,
             requests: request_source,
         })
     }
+
+    pub fn socket_path(&self) -> &Path {
+        &self.socket_path
+    }
+
+    // The directory of the socket, which is only accessible by the current user (see
+    // create_private_dir).
+    pub fn private_dir(&self) -> &Path {
+        self.socket_path
+            .parent()
+            .expect("socket is in ugdb directory")
+    }
+
+    // Describe this instance in the metadata file next to the socket. The file is replaced
+    // atomically so that clients never read a partially written file.
+    pub fn write_metadata(&self, gdb: &mut GDB) -> io::Result<()> {
+        let mut metadata = query_instance_info(gdb)
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, e.details))?;
+        metadata["start_time"] = self.start_time.into();
+        metadata["protocol_version"] = PROTOCOL_VERSION.into();
+        metadata["socket"] = self.socket_path.to_string_lossy().into_owned().into();
+
+        let path = metadata_path(&self.socket_path);
+        let mut tmp_path = path.clone().into_os_string();
+        tmp_path.push(".tmp");
+        fs::write(&tmp_path, metadata.pretty(2))?;
+        fs::rename(&tmp_path, &path)
+    }
 }
 
 impl ::std::ops::Drop for IPC {
     fn drop(&mut self) {
         // We at least try to remove the socket. If it fails we cannot really do about it here.
         let _ = fs::remove_file(&self.socket_path);
+        let _ = fs::remove_file(metadata_path(&self.socket_path));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use ipc_protocol::write_ipc_header;
+    use std::io::Write;
+
+    #[test]
+    fn test_read_ipc_request() {
+        let (mut client, mut server) = UnixStream::pair().unwrap();
+        write_ipc_message(&mut client, b"{}").unwrap();
+        let request = try_read_ipc_request(&mut server, 3)
+            .map_err(|_| ())
+            .unwrap();
+        assert_eq!(request.raw_request, Some(b"{}".to_vec()));
+        assert_eq!(request.connection.id, 3);
+
+        // Message is shorter than announced in the header
+        write_ipc_header(&mut client, 10).unwrap();
+        client.write_all(b"{}").unwrap();
+        drop(client);
+        assert!(try_read_ipc_request(&mut server, 3).is_err());
+    }
+
+    #[test]
+    fn test_create_private_dir() {
+        let dir = ::std::env::temp_dir().join(format!("ugdb-test-{}", ::std::process::id()));
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        // Permissions that are too open are fixed
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        create_private_dir(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+
+        let file = dir.join("file");
+        fs::write(&file, b"").unwrap();
+        assert!(create_private_dir(&file).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_is_same_user() {
+        let (client, _server) = UnixStream::pair().unwrap();
+        assert!(is_same_user(&client));
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let request = parse_request(
+            br#"{"id": 7, "protocol_version": 1, "function": "f", "parameters": {"a": 1}}"#,
+        )
+        .map_err(|_| ())
+        .unwrap();
+        assert_eq!(request.id, 7);
+        assert_eq!(request.function, "f");
+        assert_eq!(request.parameters["a"], 1);
+
+        let error_id = |raw: &[u8]| {
+            let (id, error) = parse_request(raw).err().expect("error");
+            (id, error.kind.as_str())
+        };
+        assert_eq!(
+            error_id(b"\xff"),
+            (json::JsonValue::Null, "malformed_request")
+        );
+        assert_eq!(
+            error_id(b"{\"function\":"),
+            (json::JsonValue::Null, "malformed_request")
+        );
+        assert_eq!(
+            error_id(b"[\"function\"]"),
+            (json::JsonValue::Null, "malformed_request")
+        );
+        assert_eq!(
+            error_id(br#"{"id": "a"}"#),
+            ("a".into(), "malformed_request")
+        );
+        assert_eq!(
+            error_id(br#"{"id": "a", "protocol_version": 2, "function": "f"}"#),
+            ("a".into(), "unsupported_version")
+        );
+    }
+
+    #[test]
+    fn test_schema_matches_implementation() {
+        let schema = json::parse(include_str!("../doc/ipc-schema.json")).unwrap();
+        assert_eq!(schema["protocol_version"], PROTOCOL_VERSION);
+
+        let mut documented = schema["functions"]
+            .entries()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        let mut implemented = FUNCTIONS.iter().map(|&(name, _)| name).collect::<Vec<_>>();
+        documented.sort();
+        implemented.sort();
+        assert_eq!(documented, implemented);
+
+        let mut documented = schema["events"]
+            .entries()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        let mut implemented = EventTopic::ALL
+            .iter()
+            .map(|t| t.as_str())
+            .collect::<Vec<_>>();
+        documented.sort();
+        implemented.sort();
+        assert_eq!(documented, implemented);
     }
 }
@@ -0,0 +1,168 @@
+// Parts of the ipc protocol that are shared by ugdb and ugdb-ctl.
+use std::ffi::OsString;
+use std::io::{ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use unix_socket::UnixStream;
+
+const FALLBACK_RUNTIME_DIR: &'static str = "/tmp/";
+const RUNTIME_SUBDIR: &'static str = "ugdb";
+const METADATA_SUFFIX: &'static str = ".json";
+const IPC_MSG_IDENTIFIER: &'static [u8] = b"ugdb-ipc";
+const HEADER_LENGTH: usize = 12;
+// Protects against allocating huge buffers for malformed headers.
+const MAX_MSG_LENGTH: u32 = 1 << 24;
+
+// Incremented for changes that are not backwards compatible. Additions can be detected by clients
+// using get_capabilities.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// The directory in which the sockets of all ugdb instances are created.
+pub fn socket_dir() -> PathBuf {
+    let runtime_dir =
+        ::std::env::var_os("XDG_RUNTIME_DIR").unwrap_or(OsString::from(FALLBACK_RUNTIME_DIR));
+    Path::join(runtime_dir.as_ref(), RUNTIME_SUBDIR)
+}
+
+// Every socket is accompanied by a file describing the instance (see get_instance_info), so that
+// clients can find the instance they are looking for without connecting to all of them.
+pub fn metadata_path(socket_path: &Path) -> PathBuf {
+    let mut path = socket_path.as_os_str().to_owned();
+    path.push(METADATA_SUFFIX);
+    path.into()
+}
+
+pub fn socket_path_of_metadata(metadata_path: &Path) -> Option<PathBuf> {
+    let path = metadata_path.to_str()?;
+    if path.ends_with(METADATA_SUFFIX) {
+        Some(path[..path.len() - METADATA_SUFFIX.len()].into())
+    } else {
+        None
+    }
+}
+
+// Sockets of instances that crashed are not removed, but nobody is listening on them anymore.
+pub fn is_stale_socket(socket_path: &Path) -> bool {
+    match UnixStream::connect(socket_path) {
+        Ok(_) => false,
+        Err(e) => e.kind() == ErrorKind::ConnectionRefused,
+    }
+}
+
+pub fn write_ipc_header<W: Write>(w: &mut W, msg_len: u32) -> Result<()> {
+    let msg_len = msg_len.to_le();
+    let msg_len_buf = [
+        msg_len as u8,
+        (msg_len >> 8) as u8,
+        (msg_len >> 16) as u8,
+        (msg_len >> 24) as u8,
+    ];
+    w.write_all(IPC_MSG_IDENTIFIER)?;
+    w.write_all(&msg_len_buf)?;
+    Ok(())
+}
+
+pub fn write_ipc_message<W: Write>(w: &mut W, msg: &[u8]) -> Result<()> {
+    write_ipc_header(w, msg.len() as u32)?;
+    w.write_all(msg)?;
+    Ok(())
+}
+
+pub fn try_read_ipc_header<R: Read>(r: &mut R) -> ::std::result::Result<u32, ()> {
+    // The header has to look like this with four bytes (****) for the message length in little endian: "ugdb-ipc****"
+    let mut buf = vec![0u8; HEADER_LENGTH];
+    r.read_exact(&mut buf).map_err(|_| {})?;
+    if &buf[0..8] == IPC_MSG_IDENTIFIER {
+        let mut len = 0;
+        len += buf[8] as u32;
+        len += (buf[9] as u32) << 8;
+        len += (buf[10] as u32) << 16;
+        len += (buf[11] as u32) << 24;
+        let len = u32::from_le(len);
+        if len <= MAX_MSG_LENGTH {
+            Ok(len)
+        } else {
+            Err(())
+        }
+    } else {
+        Err(())
+    }
+}
+
+pub fn try_read_ipc_message<R: Read>(r: &mut R) -> ::std::result::Result<Vec<u8>, ()> {
+    let msg_len = try_read_ipc_header(r)?;
+
+    let mut msg_buf = vec![0u8; msg_len as usize];
+    r.read_exact(&mut msg_buf).map_err(|_| {})?;
+    Ok(msg_buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn header(len: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_ipc_header(&mut buf, len).unwrap();
+        buf
+    }
+
+    #[test]
+    fn test_read_ipc_header() {
+        assert_eq!(header(513), b"ugdb-ipc\x01\x02\x00\x00");
+        assert_eq!(try_read_ipc_header(&mut &header(0)[..]), Ok(0));
+        assert_eq!(try_read_ipc_header(&mut &header(513)[..]), Ok(513));
+        assert_eq!(
+            try_read_ipc_header(&mut &b"ugdb-ipc\x02\x00\x00\x00{}"[..]),
+            Ok(2)
+        );
+
+        // Too short
+        assert_eq!(try_read_ipc_header(&mut &b""[..]), Err(()));
+        assert_eq!(try_read_ipc_header(&mut &b"ugdb-ipc\x02\x00"[..]), Err(()));
+        // Wrong identifier
+        assert_eq!(
+            try_read_ipc_header(&mut &b"ugdb-ipx\x02\x00\x00\x00"[..]),
+            Err(())
+        );
+        assert_eq!(
+            try_read_ipc_header(&mut &b"\x02\x00\x00\x00ugdb-ipc"[..]),
+            Err(())
+        );
+        // Too long
+        assert_eq!(
+            try_read_ipc_header(&mut &header(MAX_MSG_LENGTH + 1)[..]),
+            Err(())
+        );
+        assert_eq!(
+            try_read_ipc_header(&mut &b"ugdb-ipc\xff\xff\xff\xff"[..]),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn test_metadata_path() {
+        let socket = Path::new("/run/ugdb/my.socket");
+        assert_eq!(metadata_path(socket), Path::new("/run/ugdb/my.socket.json"));
+        assert_eq!(
+            socket_path_of_metadata(&metadata_path(socket)),
+            Some(socket.to_owned())
+        );
+        assert_eq!(socket_path_of_metadata(socket), None);
+    }
+
+    #[test]
+    fn test_read_ipc_message() {
+        let mut buf = Vec::new();
+        write_ipc_message(&mut buf, b"{}").unwrap();
+        write_ipc_message(&mut buf, b"[]").unwrap();
+        let mut reader = &buf[..];
+        assert_eq!(try_read_ipc_message(&mut reader), Ok(b"{}".to_vec()));
+        assert_eq!(try_read_ipc_message(&mut reader), Ok(b"[]".to_vec()));
+        assert_eq!(try_read_ipc_message(&mut reader), Err(()));
+
+        // Message is shorter than announced in the header
+        let mut buf = header(10);
+        buf.extend_from_slice(b"{}");
+        assert_eq!(try_read_ipc_message(&mut &buf[..]), Err(()));
+    }
+}
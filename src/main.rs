@@ -23,6 +23,9 @@ extern crate unsegen_pager;
 extern crate unsegen_signals;
 extern crate unsegen_terminal;
 
+// For parsing source code (in addition to highlighting)
+extern crate syntect;
+
 // gdbmi
 #[macro_use]
 extern crate nom;
@@ -30,10 +33,17 @@ extern crate nom;
 #[macro_use]
 extern crate lalrpop_util;
 
+mod annotations;
+mod build;
+mod dap;
+mod editor;
 mod gdb;
 mod gdb_expression_parsing;
 mod gdbmi;
 mod ipc;
+mod ipc_protocol;
+mod python_bridge;
+mod rr;
 mod tui;
 
 use std::ffi::OsString;
@@ -44,9 +54,10 @@ use chan::{Receiver, Sender};
 use chan_signal::Signal;
 
 use gdb::GDB;
-use gdbmi::output::OutOfBandRecord;
+use gdbmi::commands::TargetType;
+use gdbmi::output::{AsyncClass, OutOfBandRecord};
 use gdbmi::{GDBBuilder, OutOfBandRecordSink};
-use log::{debug, warn};
+use log::{debug, error, warn};
 use nix::sys::termios;
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -112,6 +123,13 @@ struct Options {
     core_file: Option<PathBuf>,
     #[structopt(short = "p", long = "pid", help = "Attach to process with given id.")]
     proc_id: Option<u32>,
+    #[structopt(long = "attach", help = "Select a process to attach to from a list.")]
+    attach: bool,
+    #[structopt(
+        long = "attach-name",
+        help = "Attach to the newest process whose executable name contains the given pattern."
+    )]
+    attach_name: Option<String>,
     #[structopt(
         short = "x",
         long = "command",
@@ -133,6 +151,64 @@ struct Options {
         default_value = "/tmp"
     )]
     log_dir: PathBuf,
+    #[structopt(
+        long = "rr",
+        help = "Replay the rr trace in the given directory.",
+        parse(from_os_str)
+    )]
+    rr_trace: Option<PathBuf>,
+    #[structopt(
+        long = "rr-path",
+        help = "Path to alternative rr binary.",
+        default_value = "rr",
+        parse(from_os_str)
+    )]
+    rr_path: PathBuf,
+    #[structopt(
+        long = "target",
+        help = "Connect to a remote target (e.g., a gdbserver). Type is either \"remote\" or \"extended-remote\", address is host:port or a serial device.",
+        parse(from_os_str),
+        raw(number_of_values = "2", value_names = r#"&["type", "address"]"#)
+    )]
+    target: Vec<OsString>,
+    #[structopt(
+        long = "sysroot",
+        help = "Use the given directory as the system root to find the libraries of remote targets.",
+        parse(from_os_str)
+    )]
+    sysroot: Option<PathBuf>,
+    #[structopt(
+        long = "solib-search-path",
+        help = "Colon-separated list of directories to search for shared libraries of remote targets.",
+        parse(from_os_str)
+    )]
+    solib_search_path: Option<OsString>,
+    #[structopt(
+        long = "build-command",
+        help = "Command to rebuild the program (e.g., \"cargo build\"). It is run using !build in the console or b in container selection mode."
+    )]
+    build_command: Option<String>,
+    #[structopt(
+        long = "ipc-socket-name",
+        help = "Name of the IPC socket (instead of a random one), e.g., to address this instance from scripts."
+    )]
+    ipc_socket_name: Option<String>,
+    #[structopt(
+        long = "dap",
+        help = "Serve the Debug Adapter Protocol on the given endpoint: \"stdio\" (without the TUI), host:port (loopback only) or the path of a unix socket. Tcp clients have to pass the token from $XDG_RUNTIME_DIR/ugdb/dap-<port>.token (or /tmp/ugdb/...) as \"token\" when launching or attaching."
+    )]
+    dap: Option<dap::DapEndpoint>,
+    #[structopt(
+        long = "editor-command",
+        help = "Command to open the current location of the source view in an editor (using e). {file} and {line} are replaced by the location.",
+        default_value = "$EDITOR +{line} {file}"
+    )]
+    editor_command: String,
+    #[structopt(
+        long = "editor-follow-command",
+        help = "Command that is run in the background for every stop location (e.g., to show it in a running editor). {file} and {line} are replaced by the location. Follow mode can be toggled using E in the source view."
+    )]
+    editor_follow_command: Option<String>,
     #[structopt(
         help = "Path to program to debug (with arguments).",
         parse(from_os_str)
@@ -146,6 +222,20 @@ struct Options {
 }
 
 impl Options {
+    fn remote_target(&self) -> Result<Option<(TargetType, OsString)>, String> {
+        match self.target.as_slice() {
+            [] => Ok(None),
+            [target_type, address] => {
+                let target_type = target_type
+                    .to_str()
+                    .ok_or_else(|| "Invalid target type".to_owned())?
+                    .parse::<TargetType>()?;
+                Ok(Some((target_type, address.clone())))
+            }
+            _ => Err("Only a single target can be specified".to_owned()),
+        }
+    }
+
     fn create_gdb_builder(self) -> GDBBuilder {
         let mut gdb_builder = GDBBuilder::new(self.gdb_path);
         if self.nh {
@@ -178,6 +268,12 @@ impl Options {
         if let Some(src_dir) = self.source_dir {
             gdb_builder = gdb_builder.source_dir(src_dir);
         }
+        if let Some(sysroot) = self.sysroot {
+            gdb_builder = gdb_builder.sysroot(sysroot);
+        }
+        if let Some(solib_search_path) = self.solib_search_path {
+            gdb_builder = gdb_builder.solib_search_path(solib_search_path);
+        }
         let (program, args) = self
             .program
             .split_first()
@@ -223,11 +319,39 @@ impl MessageSink {
     }
 }
 
+// Requests to the ui (that cannot be handled by the container they originate from).
+pub enum UIRequest {
+    AddWatchExpression(String),
+    ShowLocation(std::path::PathBuf, unsegen::base::LineNumber),
+    ShowProcessPicker,
+    ClearTerminal,
+}
+
+pub struct UIRequestSink {
+    requests: Vec<UIRequest>,
+}
+
+impl UIRequestSink {
+    pub fn send(&mut self, request: UIRequest) {
+        self.requests.push(request);
+    }
+    pub fn drain_requests(&mut self) -> Vec<UIRequest> {
+        let mut alt_buffer = Vec::new();
+        ::std::mem::swap(&mut self.requests, &mut alt_buffer);
+        alt_buffer
+    }
+}
+
 type UpdateParameters<'u> = &'u mut UpdateParametersStruct;
 
 pub struct UpdateParametersStruct {
     pub gdb: GDB,
     pub message_sink: MessageSink,
+    pub ui_request_sink: UIRequestSink,
+    pub build: build::Build,
+    pub editor: editor::Editor,
+    pub annotations: annotations::AnnotationSets,
+    pub event_subscriptions: ipc::EventSubscriptions,
 }
 
 // A timer that can be used to receive an event at any time,
@@ -295,6 +419,77 @@ impl InputMode {
     }
 }
 
+// The events that are handled without the TUI.
+struct HeadlessEventSources<'a> {
+    dap_messages: Receiver<dap::DapMessage>,
+    oob_source: Receiver<OutOfBandRecord>,
+    ipc_requests: &'a mut Receiver<ipc::IPCRequest>,
+    build_results: Receiver<build::BuildResult>,
+    pts_source: Receiver<Box<[u8]>>,
+    signal_event_source: Receiver<Signal>,
+}
+
+// Without the TUI, the session is controlled by a DAP client on stdin/stdout. Everything that the
+// TUI would show is sent to the client instead.
+fn run_headless(
+    p: &mut UpdateParametersStruct,
+    dap_server: &mut dap::DapServer,
+    sources: HeadlessEventSources,
+) {
+    let HeadlessEventSources {
+        dap_messages,
+        oob_source,
+        ipc_requests,
+        build_results,
+        pts_source,
+        signal_event_source,
+    } = sources;
+    let mut gdb_killed = false;
+    loop {
+        chan_select! {
+            dap_messages.recv() -> message => {
+                dap_server.handle_message(message.expect("receive dap message"), p);
+            },
+            oob_source.recv() -> oob_evt => {
+                if let Some(record) = oob_evt {
+                    dap_server.handle_out_of_band_record(&record);
+                    p.event_subscriptions.handle_out_of_band_record(&record);
+                    if let OutOfBandRecord::AsyncRecord { class: AsyncClass::BreakPoint(event), ref results, .. } = record {
+                        p.gdb.handle_breakpoint_event(event, results);
+                    }
+                } else {
+                    // OOB pipe has closed. => gdb will be stopping soon
+                    break;
+                }
+            },
+            ipc_requests.recv() -> request => {
+                request.expect("receive request").respond(p);
+            },
+            build_results.recv() -> result => {
+                result.expect("receive build result").handle(p);
+            },
+            pts_source.recv() -> pty_output => {
+                dap_server.add_program_output(&pty_output.expect("get pty input"));
+            },
+            signal_event_source.recv() -> signal_event => {
+                if signal_event == Some(Signal::TERM) {
+                    dap_server.end_session();
+                }
+            },
+        }
+        for message in p.message_sink.drain_messages() {
+            dap_server.add_message(message);
+        }
+        p.ui_request_sink.drain_requests();
+        p.event_subscriptions
+            .publish_breakpoint_changes(&mut p.gdb.breakpoints);
+        if dap_server.session_ended() && !gdb_killed {
+            p.gdb.kill();
+            gdb_killed = true;
+        }
+    }
+}
+
 fn run() -> i32 {
     // Setup signal piping:
     // NOTE: This has to be set up before the creation of any other threads!
@@ -305,29 +500,61 @@ fn run() -> i32 {
     // Set up a panic hook that ALWAYS displays panic information (including stack) to the main
     // terminal screen.
     const STDOUT: std::os::unix::io::RawFd = 0;
-    let orig_attr = std::sync::Mutex::new(
-        termios::tcgetattr(STDOUT).expect("Failed to get terminal attributes"),
-    );
+    // Without the TUI (i.e., with DAP on stdio), stdin may not be a terminal.
+    let orig_term_attr = termios::tcgetattr(STDOUT).ok();
+    let orig_attr = std::sync::Mutex::new(orig_term_attr.clone());
 
-    let options = Options::from_args();
+    let mut options = Options::from_args();
     let log_dir = options.log_dir.to_owned();
+    let remote_target = match options.remote_target() {
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 0xfb;
+        }
+    };
+    if let Some(ref pattern) = options.attach_name {
+        match tui::process_picker::newest_process_matching(pattern) {
+            Some(process) => options.proc_id = Some(process.pid),
+            None => {
+                eprintln!("No process matching '{}' found.", pattern);
+                return 0xfa;
+            }
+        }
+    }
+    let show_process_picker = options.attach;
+    let dap_endpoint = options.dap.clone();
+    let headless = dap_endpoint == Some(dap::DapEndpoint::Stdio);
+    if headless && (remote_target.is_some() || show_process_picker) {
+        eprintln!("--target and --attach require the TUI and cannot be used with --dap stdio.");
+        return 0xf7;
+    }
 
     ::std::panic::set_hook(Box::new(move |info| {
+        let report = format!(
+            "Oh no! ugdb crashed!\nConsider filing an issue including the log file located in {} and the following backtrace at {}:\n\n{}\n{:?}",
+            log_dir.to_string_lossy(),
+            env!("CARGO_PKG_REPOSITORY"),
+            info,
+            backtrace::Backtrace::new(),
+        );
+
+        // Without the TUI, stdout is the DAP channel and the terminal has not been modified.
+        if headless {
+            error!("{}", report);
+            eprintln!("{}", report);
+            return;
+        }
+
         // Switch back to main screen
         println!("{}{}", termion::screen::ToMainScreen, termion::cursor::Show);
         // Restore old terminal behavior (will be restored later automatically, but we want to be
         // able to properly print the panic info)
-        let _ = termios::tcsetattr(STDOUT, termios::SetArg::TCSANOW, &orig_attr.lock().unwrap());
-
-        println!("Oh no! ugdb crashed!");
-        println!(
-            "Consider filing an issue including the log file located in {} and the following backtrace at {}:\n",
-            log_dir.to_string_lossy(),
-            env!("CARGO_PKG_REPOSITORY"),
-        );
+        if let Some(ref orig_attr) = *orig_attr.lock().unwrap() {
+            let _ = termios::tcsetattr(STDOUT, termios::SetArg::TCSANOW, orig_attr);
+        }
 
-        println!("{}", info);
-        println!("{:?}", backtrace::Backtrace::new());
+        println!("{}", report);
     }));
 
     if let Err(e) = flexi_logger::Logger::with_env_or_str("info")
@@ -345,18 +572,76 @@ fn run() -> i32 {
         ::unsegen_terminal::Terminal::new(MpscSlaveInputSink(pts_sink)).expect("Create PTY");
 
     // Setup ipc
-    let mut ipc = ipc::IPC::setup().expect("Setup ipc");
+    let mut ipc = match ipc::IPC::setup(options.ipc_socket_name.clone()) {
+        Ok(ipc) => ipc,
+        Err(e) => {
+            eprintln!("Unable to setup IPC: {}", e);
+            return 0xf9;
+        }
+    };
+
+    // Setup dap
+    let (mut dap_server, dap_messages) = dap::DapServer::new();
+    if let Some(ref endpoint) = dap_endpoint {
+        if let Err(e) = dap_server.start(endpoint, ipc.private_dir()) {
+            eprintln!("Unable to start DAP server: {}", e);
+            return 0xf8;
+        }
+    }
 
     // Start gdb and setup output event piping
     let (oob_sink, oob_source) = chan::async();
 
+    let rr_trace = options.rr_trace.clone();
+    let rr_path = options.rr_path.clone();
+    let program_specified = !options.program.is_empty();
+    let (build, build_results) = build::Build::new(
+        options.build_command.clone(),
+        tui_terminal.slave_name().to_owned(),
+    );
+    let editor = editor::Editor::new(
+        options.editor_command.clone(),
+        options.editor_follow_command.clone(),
+    );
     let mut gdb_builder = options.create_gdb_builder();
     gdb_builder = gdb_builder.tty(tui_terminal.slave_name().into());
-    let gdb = GDB::new(
+
+    // Has to be kept alive until gdb exits.
+    let rr_replay = if let Some(trace_dir) = rr_trace {
+        match rr::RRReplay::launch(
+            &rr_path,
+            &trace_dir,
+            tui_terminal.slave_name(),
+            ipc.private_dir(),
+        ) {
+            Ok(replay) => {
+                gdb_builder = replay.configure_gdb(gdb_builder, !program_specified);
+                Some(replay)
+            }
+            Err(e) => {
+                eprintln!("Unable to launch rr replay: {}", e);
+                return 0xfc;
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut gdb = GDB::new(
         gdb_builder
             .try_spawn(MpscOobRecordSink(oob_sink))
             .expect("spawn gdb"),
     );
+    if rr_replay.is_some() {
+        gdb.record.start_replay_session("rr");
+    }
+    if let Err(e) = ipc.write_metadata(&mut gdb) {
+        warn!("Unable to write ipc metadata: {}", e);
+    }
+    // Fails if gdb was built without python support, which only affects gdb scripts using it.
+    if let Err(e) = python_bridge::install(&mut gdb, ipc.socket_path()) {
+        warn!("Unable to install ugdb python module: {}", e);
+    }
 
     // Setup input piping
     let (keyboard_sink, keyboard_source) = chan::async();
@@ -380,9 +665,29 @@ fn run() -> i32 {
         message_sink: MessageSink {
             messages: Vec::new(),
         },
+        ui_request_sink: UIRequestSink {
+            requests: Vec::new(),
+        },
+        build: build,
+        editor: editor,
+        annotations: annotations::AnnotationSets::new(),
+        event_subscriptions: ipc::EventSubscriptions::new(),
     };
 
-    {
+    if headless {
+        run_headless(
+            &mut update_parameters,
+            &mut dap_server,
+            HeadlessEventSources {
+                dap_messages: dap_messages,
+                oob_source: oob_source,
+                ipc_requests: &mut ipc.requests,
+                build_results: build_results,
+                pts_source: pts_source,
+                signal_event_source: signal_event_source,
+            },
+        );
+    } else {
         let mut terminal = match Terminal::new(stdout.lock()) {
             Ok(t) => t,
             Err(e) => {
@@ -392,12 +697,19 @@ fn run() -> i32 {
         };
         let mut tui = Tui::new(tui_terminal, &theme_set.themes["base16-ocean.dark"]);
 
+        if let Some((target_type, address)) = remote_target {
+            tui.connect_to_target(target_type, &address, &mut update_parameters);
+        }
+        if show_process_picker {
+            tui.console.show_process_picker();
+        }
+
         // Start stdin thread _after_ building terminal (and setting the actual terminal to raw
         // mode to avoid race condition where the first 'set of input' is buffered
+        let keyboard_pause = editor::KeyboardPause::new();
+        let stdin = editor::PausableStdin::new(keyboard_pause.clone());
         /* let keyboard_input = */
         ::std::thread::spawn(move || {
-            let stdin = ::std::io::stdin();
-            let stdin = stdin.lock();
             for e in Input::read_all(stdin) {
                 keyboard_sink.send(e.expect("event"));
             }
@@ -463,6 +775,8 @@ fn run() -> i32 {
                                         .chain((Key::Char('s'), || { input_mode = InputMode::Normal; app.set_active(TuiContainerType::SrcView); }))
                                         .chain((Key::Char('t'), || { input_mode = InputMode::Normal; app.set_active(TuiContainerType::Terminal); }))
                                         .chain((Key::Char('T'), || { input_mode = InputMode::Focused; app.set_active(TuiContainerType::Terminal); }))
+                                        .chain((Key::Char('b'), || build::start_build(false, &mut update_parameters)))
+                                        .chain((Key::Char('B'), || build::start_build(true, &mut update_parameters)))
                                         .chain((Key::Char('\n'), || input_mode = InputMode::Normal ))
                                 }
                                 InputMode::Normal => {
@@ -479,6 +793,7 @@ fn run() -> i32 {
                         },
                         oob_source.recv() -> oob_evt => {
                             if let Some(record) = oob_evt {
+                                dap_server.handle_out_of_band_record(&record);
                                 tui.add_out_of_band_record(record, &mut update_parameters);
                             } else {
                                 // OOB pipe has closed. => gdb will be stopping soon
@@ -488,6 +803,12 @@ fn run() -> i32 {
                         ipc_requests.recv() -> request => {
                             request.expect("receive request").respond(&mut update_parameters);
                         },
+                        dap_messages.recv() -> message => {
+                            dap_server.handle_message(message.expect("receive dap message"), &mut update_parameters);
+                        },
+                        build_results.recv() -> result => {
+                            result.expect("receive build result").handle(&mut update_parameters);
+                        },
                         pts_source.recv() -> pty_output => {
                             tui.add_pty_input(&pty_output.expect("get pty input"));
                         },
@@ -514,6 +835,21 @@ fn run() -> i32 {
                     }
                 }
                 tui.update_after_event(&mut update_parameters);
+                if let Some(command) = update_parameters.editor.take_pending() {
+                    match editor::run_in_foreground(
+                        &command,
+                        orig_term_attr.as_ref(),
+                        &keyboard_pause,
+                    ) {
+                        Ok(ref status) if status.success() => {}
+                        Ok(status) => update_parameters
+                            .message_sink
+                            .send(format!("Editor exited with {}.", status)),
+                        Err(e) => update_parameters
+                            .message_sink
+                            .send(format!("Unable to run editor: {}", e)),
+                    }
+                }
                 render_delay_timer.try_start(Duration::from_millis(EVENT_BUFFER_DURATION_MS));
             }
             if esc_timer_needs_reset {
@@ -0,0 +1,96 @@
+# Lets gdb python scripts (e.g., breakpoint commands or pretty printers) control the user interface
+# of ugdb via its ipc socket. ugdb installs this module into gdb at startup, so it can be used
+# without importing it, e.g., `python ugdb.message("Hello")` in the console.
+#
+# Requests are sent without waiting for their responses: ugdb handles requests only when it is not
+# waiting for gdb itself, which is not the case while a console command is executed or a pretty
+# printer is called. Errors are therefore reported on one of the subsequent calls.
+import json
+import socket
+import struct
+
+import gdb
+
+_IPC_MSG_IDENTIFIER = b"ugdb-ipc"
+_HEADER_LENGTH = 12
+
+# Set by ugdb when installing the module.
+_socket_path = None
+_connection = None
+_received = b""
+
+
+def _close():
+    global _connection, _received
+    _connection.close()
+    _connection = None
+    _received = b""
+
+
+def _report_errors():
+    global _received
+    while _connection is not None:
+        try:
+            data = _connection.recv(4096, socket.MSG_DONTWAIT)
+        except socket.error:
+            # No responses are pending.
+            break
+        if not data:
+            # ugdb closed the connection (after a malformed request).
+            _close()
+            return
+        _received += data
+    while len(_received) >= _HEADER_LENGTH:
+        (length,) = struct.unpack("<I", _received[len(_IPC_MSG_IDENTIFIER):_HEADER_LENGTH])
+        if len(_received) < _HEADER_LENGTH + length:
+            break
+        message = _received[_HEADER_LENGTH:_HEADER_LENGTH + length]
+        _received = _received[_HEADER_LENGTH + length:]
+        response = json.loads(message.decode("utf-8"))
+        if response.get("type") == "error":
+            gdb.write("ugdb: {}: {}\n".format(response["reason"], response["details"]), gdb.STDERR)
+
+
+def request(function, **parameters):
+    """Call the given ipc function (see doc/ipc-schema.json) without waiting for the result."""
+    global _connection
+    if _connection is None:
+        connection = socket.socket(socket.AF_UNIX, socket.SOCK_STREAM)
+        try:
+            connection.connect(_socket_path)
+        except socket.error as e:
+            connection.close()
+            raise gdb.GdbError("ugdb: Unable to connect to {}: {}".format(_socket_path, e))
+        _connection = connection
+    else:
+        _report_errors()
+        if _connection is None:
+            return request(function, **parameters)
+    message = json.dumps({"function": function, "parameters": parameters}).encode("utf-8")
+    _connection.sendall(_IPC_MSG_IDENTIFIER + struct.pack("<I", len(message)) + message)
+
+
+def watch(expression):
+    """Add the expression to the expression table."""
+    request("add_watch_expression", expression=str(expression))
+
+
+def show(file, line):
+    """Show the given line of the file in the pager."""
+    request("show_location", file=str(file), line=int(line))
+
+
+def message(text):
+    """Show the text in the console."""
+    request("show_message", text=str(text))
+
+
+def annotate(file, line, text="", severity="info", set="gdb"):
+    """Annotate the line of the file in the source view (severity is info, warning or error)."""
+    annotation = {"file": str(file), "line": int(line), "severity": severity, "text": str(text)}
+    request("add_annotations", set=set, annotations=[annotation])
+
+
+def clear_annotations(set="gdb"):
+    """Remove all annotations of the set."""
+    request("set_annotations", set=set, annotations=[])
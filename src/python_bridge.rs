@@ -0,0 +1,66 @@
+// The ugdb python module (see python/ugdb.py) is installed into gdb at startup, so that gdb
+// scripts can control the user interface via the ipc socket.
+use gdb::GDB;
+use gdbmi::commands::MiCommand;
+use gdbmi::output::ResultClass;
+use gdbmi::ExecuteError;
+use json;
+use std::fmt;
+use std::path::Path;
+
+const MODULE_SOURCE: &str = include_str!("python/ugdb.py");
+
+#[derive(Debug)]
+pub enum InstallError {
+    Execution(ExecuteError),
+    Gdb(String),
+}
+
+impl fmt::Display for InstallError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &InstallError::Execution(ref e) => write!(f, "{:?}", e),
+            &InstallError::Gdb(ref msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+// The module is created from its source in a single line of python (instead of a file that would
+// have to be cleaned up). Json string literals are valid python string literals.
+fn install_command(socket_path: &Path) -> String {
+    format!(
+        "python import sys, types; ugdb = types.ModuleType(\"ugdb\"); exec({}, ugdb.__dict__); ugdb._socket_path = {}; sys.modules[\"ugdb\"] = ugdb",
+        json::stringify(MODULE_SOURCE),
+        json::stringify(socket_path.to_string_lossy().into_owned())
+    )
+}
+
+pub fn install(gdb: &mut GDB, socket_path: &Path) -> Result<(), InstallError> {
+    let result = gdb
+        .mi
+        .execute(MiCommand::cli_exec(&install_command(socket_path)))
+        .map_err(InstallError::Execution)?;
+    match result.class {
+        ResultClass::Error => Err(InstallError::Gdb(
+            result.results["msg"]
+                .as_str()
+                .unwrap_or("Unknown error")
+                .to_owned(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_install_command_is_single_line() {
+        let command = install_command(Path::new("/tmp/ugdb/\"quoted\"\n"));
+        assert!(!command.contains('\n'));
+        assert!(command.ends_with(
+            "ugdb._socket_path = \"/tmp/ugdb/\\\"quoted\\\"\\n\"; sys.modules[\"ugdb\"] = ugdb"
+        ));
+    }
+}
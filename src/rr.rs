@@ -0,0 +1,276 @@
+// Support for using ugdb as a frontend for rr: `rr replay` is started as a gdbserver and gdb is
+// connected to it using the commands that rr suggests when it is launched.
+use gdbmi::GDBBuilder;
+use log::info;
+use std::ffi::OsStr;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{self, Child, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// rr prints the instructions to launch gdb once the trace is loaded and it listens for gdb.
+const LAUNCH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+pub enum RRError {
+    Io(io::Error),
+    NoLaunchInstructions,
+    LaunchTimeout,
+}
+
+impl From<io::Error> for RRError {
+    fn from(e: io::Error) -> Self {
+        RRError::Io(e)
+    }
+}
+
+impl fmt::Display for RRError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &RRError::Io(ref e) => write!(f, "{}", e),
+            &RRError::NoLaunchInstructions => {
+                write!(f, "rr exited without printing instructions to launch gdb")
+            }
+            &RRError::LaunchTimeout => write!(
+                f,
+                "rr did not print instructions to launch gdb within {} seconds",
+                LAUNCH_TIMEOUT.as_secs()
+            ),
+        }
+    }
+}
+
+pub struct RRReplay {
+    process: Child,
+    gdb_init_commands: Vec<String>,
+    program: Option<PathBuf>,
+    gdbinit_file: Option<PathBuf>,
+}
+
+impl RRReplay {
+    // The output of the replayed program is written to `tty`. Temporary files are created in
+    // `private_dir`, which must only be accessible by the current user.
+    pub fn launch(
+        rr_path: &Path,
+        trace_dir: &Path,
+        tty: &OsStr,
+        private_dir: &Path,
+    ) -> Result<Self, RRError> {
+        // Let the OS pick a port that is (very likely) still free when rr binds it.
+        let port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let output = OpenOptions::new().write(true).open(tty)?;
+        let mut process = Command::new(rr_path)
+            .arg("replay")
+            .arg(format!("--dbgport={}", port))
+            .arg(trace_dir)
+            .stdin(Stdio::null())
+            .stdout(output)
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // rr will block if nobody reads its output, so it is read in a separate thread for as
+        // long as rr is running.
+        let lines = BufReader::new(process.stderr.take().expect("take stderr")).lines();
+        let (launch_args_sink, launch_args_source) = mpsc::channel();
+        thread::Builder::new()
+            .name("rr output".to_owned())
+            .spawn(move || {
+                let mut launch_args_sink = Some(launch_args_sink);
+                for line in lines {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    if launch_args_sink.is_some() {
+                        if let Some(args) = parse_launch_line(&line) {
+                            let _ = launch_args_sink.take().unwrap().send(args);
+                            continue;
+                        }
+                    }
+                    info!("rr: {}", line);
+                }
+            })?;
+        let launch_args = match launch_args_source.recv_timeout(LAUNCH_TIMEOUT) {
+            Ok(args) => args,
+            Err(e) => {
+                let _ = process.kill();
+                let _ = process.wait();
+                return Err(match e {
+                    mpsc::RecvTimeoutError::Timeout => RRError::LaunchTimeout,
+                    mpsc::RecvTimeoutError::Disconnected => RRError::NoLaunchInstructions,
+                });
+            }
+        };
+
+        let (mut gdb_init_commands, program) = gdb_options(&launch_args);
+
+        // rr defines additional commands (e.g., "when") in a gdb script that would be sourced if
+        // rr started gdb itself.
+        let gdbinit_file = Command::new(rr_path)
+            .arg("gdbinit")
+            .output()
+            .ok()
+            .and_then(|output| {
+                if !output.status.success() {
+                    return None;
+                }
+                let path = private_dir.join(format!("rr-gdbinit-{}", process::id()));
+                match write_new_file(&path, &output.stdout) {
+                    Ok(()) => Some(path),
+                    Err(e) => {
+                        info!(
+                            "Unable to write rr gdbinit {}: {}",
+                            path.to_string_lossy(),
+                            e
+                        );
+                        None
+                    }
+                }
+            });
+        if let Some(ref path) = gdbinit_file {
+            gdb_init_commands.insert(0, format!("source {}", path.to_string_lossy()));
+        }
+
+        Ok(RRReplay {
+            process: process,
+            gdb_init_commands: gdb_init_commands,
+            program: program,
+            gdbinit_file: gdbinit_file,
+        })
+    }
+
+    pub fn configure_gdb(&self, mut gdb_builder: GDBBuilder, set_program: bool) -> GDBBuilder {
+        if let (true, Some(program)) = (set_program, self.program.as_ref()) {
+            gdb_builder = gdb_builder.program(program.clone());
+        }
+        for command in &self.gdb_init_commands {
+            gdb_builder = gdb_builder.init_command(command.as_str());
+        }
+        gdb_builder
+    }
+}
+
+impl Drop for RRReplay {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        if let Some(ref path) = self.gdbinit_file {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+// The file must not exist already, so that we never write to (or source) a file prepared by
+// someone else.
+fn write_new_file(path: &Path, content: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    if let Err(e) = file.write_all(content) {
+        let _ = fs::remove_file(path);
+        return Err(e);
+    }
+    Ok(())
+}
+
+// Split a line into words like a shell would (for the subset of quoting that rr uses).
+fn split_shell_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current: Option<String> = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = current.get_or_insert_with(String::new);
+                while let Some(c) = chars.next() {
+                    if c == '\'' {
+                        break;
+                    }
+                    word.push(c);
+                }
+            }
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    current.get_or_insert_with(String::new).push(c);
+                }
+            }
+            c if c.is_whitespace() => {
+                if let Some(word) = current.take() {
+                    words.push(word);
+                }
+            }
+            c => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if let Some(word) = current {
+        words.push(word);
+    }
+    words
+}
+
+// rr prints a line like "gdb '-l' '10000' '-ex' 'target extended-remote 127.0.0.1:1234' prog"
+fn parse_launch_line(line: &str) -> Option<Vec<String>> {
+    let mut words = split_shell_words(line);
+    if words.first().map(|w| w == "gdb").unwrap_or(false) && words.len() > 1 {
+        words.remove(0);
+        Some(words)
+    } else {
+        None
+    }
+}
+
+fn gdb_options(args: &[String]) -> (Vec<String>, Option<PathBuf>) {
+    let mut commands = Vec::new();
+    let mut program = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-ex" | "--eval-command" => commands.extend(args.next().cloned()),
+            "-l" => {
+                if let Some(timeout) = args.next() {
+                    commands.push(format!("set remotetimeout {}", timeout));
+                }
+            }
+            other if other.starts_with('-') => info!("Ignoring gdb option of rr: {}", other),
+            other => program = Some(PathBuf::from(other)),
+        }
+    }
+    (commands, program)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_launch_line() {
+        let args = parse_launch_line(
+            "  gdb '-l' '10000' '-ex' 'set sysroot /' '-ex' 'target extended-remote 127.0.0.1:4242' /tmp/trace/mmap_hardlink_3_prog",
+        )
+        .unwrap();
+        assert_eq!(
+            gdb_options(&args),
+            (
+                vec![
+                    "set remotetimeout 10000".to_owned(),
+                    "set sysroot /".to_owned(),
+                    "target extended-remote 127.0.0.1:4242".to_owned(),
+                ],
+                Some(PathBuf::from("/tmp/trace/mmap_hardlink_3_prog"))
+            )
+        );
+        assert_eq!(parse_launch_line("Launch gdb with"), None);
+        assert_eq!(
+            split_shell_words(r"'it'\''s' a\ b"),
+            vec!["it's".to_owned(), "a b".to_owned()]
+        );
+    }
+}
@@ -2,7 +2,9 @@ use gdbmi::commands::MiCommand;
 use gdbmi::output::{ResultClass, ResultRecord};
 use gdbmi::ExecuteError;
 
+use build::start_build;
 use log::error;
+use UIRequest;
 
 pub struct Command {
     cmd: Box<dyn FnMut(::UpdateParameters) -> Result<(), ExecuteError>>,
@@ -12,15 +14,6 @@ impl Command {
     fn new(cmd: Box<dyn FnMut(::UpdateParameters) -> Result<(), ExecuteError>>) -> Command {
         Command { cmd: cmd }
     }
-    fn from_mi_with_msg(cmd: MiCommand, success_msg: &'static str) -> Command {
-        Command::new(Box::new(move |p: ::UpdateParameters| {
-            let res = p.gdb.mi.execute(cmd.clone()).map(|_| ());
-            if res.is_ok() {
-                p.message_sink.send(success_msg);
-            }
-            res
-        }))
-    }
     fn from_mi(cmd: MiCommand) -> Command {
         Command::new(Box::new(move |p: ::UpdateParameters| {
             p.gdb.mi.execute(cmd.clone()).map(|_| ())
@@ -28,6 +21,42 @@ impl Command {
     }
 }
 
+// Execute the command and report a failure to the user. Returns whether the command succeeded.
+fn execute_reporting_errors(
+    cmd: MiCommand,
+    error_msg: &str,
+    p: ::UpdateParameters,
+) -> Result<bool, ExecuteError> {
+    let res = p.gdb.mi.execute(cmd)?;
+    if res.class == ResultClass::Error {
+        p.message_sink.send(format!(
+            "{}: {}",
+            error_msg,
+            res.results["msg"].as_str().unwrap_or("Unknown error")
+        ));
+        Ok(false)
+    } else {
+        Ok(true)
+    }
+}
+
+// Kill the program (if it is running) and run it again. Breakpoints are kept by gdb and the
+// expression table is not touched, so both survive the restart.
+pub fn restart_program(p: ::UpdateParameters) -> Result<(), ExecuteError> {
+    if p.gdb.mi.is_session_active()? {
+        if !execute_reporting_errors(MiCommand::cli_exec("kill"), "Unable to kill program", p)? {
+            return Ok(());
+        }
+    }
+    // Code may be loaded at different addresses in the new process.
+    p.gdb.disassembly_cache.invalidate();
+    p.ui_request_sink.send(UIRequest::ClearTerminal);
+    if execute_reporting_errors(MiCommand::exec_run(), "Unable to run program", p)? {
+        p.message_sink.send("Restarted program.");
+    }
+    Ok(())
+}
+
 pub enum CommandState {
     Idle,
     WaitingForConfirmation(Command),
@@ -102,7 +131,7 @@ impl CommandState {
         } else {
             return CommandState::Idle;
         };
-        let _arguments = cmd_split.collect::<Vec<_>>();
+        let arguments = cmd_split.filter(|arg| !arg.is_empty()).collect::<Vec<_>>();
         match cmd {
             "!stop" => {
                 p.gdb.mi.interrupt_execution().expect("interrupted gdb");
@@ -113,10 +142,18 @@ impl CommandState {
             }
             "!reload" => match p.gdb.get_target() {
                 Ok(Some(target)) => Self::ask_if_session_active(
-                    Command::from_mi_with_msg(
-                        MiCommand::file_exec_and_symbols(&target),
-                        "Reloaded target.",
-                    ),
+                    Command::new(Box::new(move |p: ::UpdateParameters| {
+                        if execute_reporting_errors(
+                            MiCommand::file_exec_and_symbols(&target),
+                            "Unable to reload target",
+                            p,
+                        )? {
+                            // Code may change with the reloaded target.
+                            p.gdb.disassembly_cache.invalidate();
+                            p.message_sink.send("Reloaded target.");
+                        }
+                        Ok(())
+                    })),
                     "Reload anyway?",
                     p,
                 ),
@@ -130,6 +167,111 @@ impl CommandState {
                     CommandState::Idle
                 }
             },
+            "!restart" => Self::ask_if_session_active(
+                Command::new(Box::new(restart_program)),
+                "Restart anyway?",
+                p,
+            ),
+            "!kill" => Self::ask_if_session_active(
+                Command::new(Box::new(|p: ::UpdateParameters| {
+                    if execute_reporting_errors(
+                        MiCommand::cli_exec("kill"),
+                        "Unable to kill program",
+                        p,
+                    )? {
+                        p.message_sink.send("Killed program.");
+                    }
+                    Ok(())
+                })),
+                "Kill anyway?",
+                p,
+            ),
+            "!detach" => Self::ask_if_session_active(
+                Command::new(Box::new(|p: ::UpdateParameters| {
+                    if execute_reporting_errors(MiCommand::target_detach(), "Unable to detach", p)?
+                    {
+                        p.message_sink.send("Detached from program.");
+                    }
+                    Ok(())
+                })),
+                "Detach anyway?",
+                p,
+            ),
+            "!args" if arguments.is_empty() => {
+                match p.gdb.mi.execute(MiCommand::gdb_show("args")) {
+                    Ok(res) => p.message_sink.send(format!(
+                        "Program arguments: {}",
+                        res.results["value"].as_str().unwrap_or("")
+                    )),
+                    Err(e) => Self::print_execute_error(e, p),
+                }
+                CommandState::Idle
+            }
+            "!args" => {
+                // The arguments are passed to gdb unchanged, so that they are split (and expanded)
+                // by the shell that starts the program, just like the arguments of `run`.
+                let set_args =
+                    MiCommand::cli_exec(&format!("set args {}", line[cmd.len()..].trim()));
+                match p.gdb.mi.is_session_active() {
+                    Ok(true) => {
+                        p.message_sink.send(
+                            "A debugging session is active. Restart with new arguments? (y or n)",
+                        );
+                        CommandState::WaitingForConfirmation(Command::new(Box::new(
+                            move |p: ::UpdateParameters| {
+                                if execute_reporting_errors(
+                                    set_args.clone(),
+                                    "Unable to set arguments",
+                                    p,
+                                )? {
+                                    restart_program(p)?;
+                                }
+                                Ok(())
+                            },
+                        )))
+                    }
+                    Ok(false) => {
+                        match execute_reporting_errors(set_args, "Unable to set arguments", p) {
+                            Ok(true) => p.message_sink.send("Set program arguments."),
+                            Ok(false) => {}
+                            Err(e) => Self::print_execute_error(e, p),
+                        }
+                        CommandState::Idle
+                    }
+                    Err(e) => {
+                        Self::print_execute_error(e, p);
+                        CommandState::Idle
+                    }
+                }
+            }
+            "!build" => {
+                start_build(arguments.first() == Some(&"restart"), p);
+                CommandState::Idle
+            }
+            "!attach" => {
+                p.ui_request_sink.send(UIRequest::ShowProcessPicker);
+                CommandState::Idle
+            }
+            "!load" => {
+                // The code on the target changes.
+                p.gdb.disassembly_cache.invalidate();
+                Self::try_execute(
+                    Command::new(Box::new(|p: ::UpdateParameters| {
+                        let res = p.gdb.mi.execute(MiCommand::target_download())?;
+                        if res.class == ResultClass::Error {
+                            p.message_sink.send(format!(
+                                "Unable to download program: {}",
+                                res.results["msg"].as_str().unwrap_or("Unknown error")
+                            ));
+                        } else {
+                            p.message_sink.send("Downloaded program to target.");
+                        }
+                        Ok(())
+                    })),
+                    p,
+                );
+                CommandState::Idle
+            }
             "q" => {
                 Self::ask_if_session_active(Command::from_mi(MiCommand::exit()), "Quit anyway?", p)
             }
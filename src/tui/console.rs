@@ -1,8 +1,11 @@
+use gdbmi::commands::MiCommand;
+use gdbmi::output::ResultClass;
 use tui::commands::CommandState;
+use tui::process_picker::ProcessPicker;
 
 use unsegen::base::{GraphemeCluster, Window};
 use unsegen::container::Container;
-use unsegen::input::{EditBehavior, Input, Key, ScrollBehavior};
+use unsegen::input::{EditBehavior, Editable, Input, Key, ScrollBehavior};
 use unsegen::widget::builtin::{LogViewer, PromptLine};
 use unsegen::widget::{Demand2D, RenderingHints, SeparatingStyle, VerticalLayout, Widget};
 
@@ -18,10 +21,13 @@ pub struct Console {
     layout: VerticalLayout,
     last_gdb_state: GDBState,
     command_state: CommandState,
+    // Replaces the gdb log while a process to attach to is selected.
+    process_picker: Option<ProcessPicker>,
 }
 
 static STOPPED_PROMPT: &'static str = "(gdb) ";
 static RUNNING_PROMPT: &'static str = "(↻↻↻) ";
+static PROCESS_FILTER_PROMPT: &'static str = "(attach) ";
 
 impl Console {
     pub fn new() -> Self {
@@ -33,9 +39,97 @@ impl Console {
             )),
             last_gdb_state: GDBState::Stopped,
             command_state: CommandState::Idle,
+            process_picker: None,
         }
     }
 
+    pub fn show_process_picker(&mut self) {
+        let _ = self.prompt_line.clear();
+        self.prompt_line
+            .set_prompt(PROCESS_FILTER_PROMPT.to_owned());
+        self.process_picker = Some(ProcessPicker::new());
+        self.write_to_gdb_log(
+            "Select a process to attach to (type to filter, Enter: attach, Ctrl-C: cancel).\n",
+        );
+    }
+
+    fn close_process_picker(&mut self) {
+        self.process_picker = None;
+        let _ = self.prompt_line.clear();
+        self.prompt_line.set_prompt(
+            match self.last_gdb_state {
+                GDBState::Running => RUNNING_PROMPT,
+                GDBState::Stopped => STOPPED_PROMPT,
+            }
+            .to_owned(),
+        );
+    }
+
+    fn attach_to_selected_process(&mut self, p: ::UpdateParameters) {
+        let pid = self
+            .process_picker
+            .as_ref()
+            .and_then(|picker| picker.selected_process())
+            .map(|process| process.pid);
+        self.close_process_picker();
+        let pid = if let Some(pid) = pid {
+            pid
+        } else {
+            self.write_to_gdb_log("No matching process.\n");
+            return;
+        };
+        match p.gdb.mi.execute(MiCommand::target_attach(pid)) {
+            Ok(ref result) if result.class == ResultClass::Error => {
+                p.message_sink.send(format!(
+                    "Unable to attach to process {}: {}",
+                    pid,
+                    result.results["msg"].as_str().unwrap_or("Unknown error")
+                ));
+            }
+            Ok(_) => {
+                // The code of the new process may differ from what we have seen before.
+                p.gdb.disassembly_cache.invalidate();
+                p.message_sink.send(format!("Attached to process {}.", pid));
+            }
+            Err(_) => {
+                p.message_sink.send("Cannot attach: Gdb is busy.");
+            }
+        }
+    }
+
+    fn process_picker_event(&mut self, input: Input, p: ::UpdateParameters) -> Option<Input> {
+        let mut attach = false;
+        let mut cancel = false;
+        let ret = if let Some(ref mut picker) = self.process_picker {
+            input
+                .chain((Key::Char('\n'), || attach = true))
+                .chain((Key::Ctrl('c'), || cancel = true))
+                .chain((Key::Up, || picker.select_previous()))
+                .chain((Key::Down, || picker.select_next()))
+                .chain(
+                    EditBehavior::new(&mut self.prompt_line)
+                        .left_on(Key::Left)
+                        .right_on(Key::Right)
+                        .delete_forwards_on(Key::Delete)
+                        .delete_backwards_on(Key::Backspace)
+                        .go_to_beginning_of_line_on(Key::Home)
+                        .go_to_end_of_line_on(Key::End),
+                )
+                .finish()
+        } else {
+            return Some(input);
+        };
+        if let Some(ref mut picker) = self.process_picker {
+            picker.set_filter(self.prompt_line.active_line());
+        }
+        if attach {
+            self.attach_to_selected_process(p);
+        } else if cancel {
+            self.close_process_picker();
+        }
+        ret
+    }
+
     pub fn display_messages(&mut self, sink: &mut ::MessageSink) {
         use std::fmt::Write;
         for msg in sink.drain_messages() {
@@ -61,12 +155,16 @@ impl Console {
         if p.gdb.mi.is_running() {
             if self.last_gdb_state != GDBState::Running {
                 self.last_gdb_state = GDBState::Running;
-                self.prompt_line.set_prompt(RUNNING_PROMPT.to_owned());
+                if self.process_picker.is_none() {
+                    self.prompt_line.set_prompt(RUNNING_PROMPT.to_owned());
+                }
             }
         } else {
             if self.last_gdb_state != GDBState::Stopped {
                 self.last_gdb_state = GDBState::Stopped;
-                self.prompt_line.set_prompt(STOPPED_PROMPT.to_owned());
+                if self.process_picker.is_none() {
+                    self.prompt_line.set_prompt(STOPPED_PROMPT.to_owned());
+                }
             }
         }
     }
@@ -74,19 +172,28 @@ impl Console {
 
 impl Widget for Console {
     fn space_demand(&self) -> Demand2D {
-        let widgets: Vec<&dyn Widget> = vec![&self.gdb_log, &self.prompt_line];
+        let log: &dyn Widget = if let Some(ref picker) = self.process_picker {
+            picker
+        } else {
+            &self.gdb_log
+        };
+        let widgets: Vec<&dyn Widget> = vec![log, &self.prompt_line];
         self.layout.space_demand(widgets.as_slice())
     }
     fn draw(&self, window: Window, hints: RenderingHints) {
-        self.layout.draw(
-            window,
-            &[(&self.gdb_log, hints), (&self.prompt_line, hints)],
-        )
+        let log: &dyn Widget = if let Some(ref picker) = self.process_picker {
+            picker
+        } else {
+            &self.gdb_log
+        };
+        self.layout
+            .draw(window, &[(log, hints), (&self.prompt_line, hints)])
     }
 }
 impl Container<::UpdateParametersStruct> for Console {
     fn input(&mut self, input: Input, p: ::UpdateParameters) -> Option<Input> {
         input
+            .chain(|i: Input| self.process_picker_event(i, p))
             .chain((Key::Char('\n'), || self.handle_newline(p)))
             .chain(
                 EditBehavior::new(&mut self.prompt_line)
@@ -1,7 +1,5 @@
 use self::json_ext::JsonValue;
-use gdb_expression_parsing::parse_gdb_value;
-use gdbmi::commands::MiCommand;
-use gdbmi::output::ResultClass;
+use gdb::EvaluationError;
 use gdbmi::ExecuteError;
 use unsegen::base::{Color, GraphemeCluster, StyleModifier, Window};
 use unsegen::container::Container;
@@ -114,24 +112,13 @@ impl ExpressionTable {
             let result = if expr.is_empty() {
                 JsonValue::Null
             } else {
-                match p.gdb.mi.execute(MiCommand::data_evaluate_expression(expr)) {
-                    Ok(res) => match res.class {
-                        ResultClass::Error => res.results["msg"].clone(),
-                        ResultClass::Done => {
-                            let to_parse = res.results["value"].as_str().expect("value present");
-                            match parse_gdb_value(to_parse) {
-                                Ok(p) => p,
-                                Err(_) => {
-                                    JsonValue::String(format!("*Error parsing*: {}", to_parse))
-                                }
-                            }
-                        }
-                        other => panic!("unexpected result class: {:?}", other),
-                    },
-                    Err(ExecuteError::Busy) => {
+                match p.gdb.evaluate_expression(expr) {
+                    Ok(value) => value,
+                    Err(EvaluationError::Gdb(msg)) => JsonValue::String(msg),
+                    Err(EvaluationError::Execution(ExecuteError::Busy)) => {
                         return;
                     }
-                    Err(ExecuteError::Quit) => {
+                    Err(EvaluationError::Execution(ExecuteError::Quit)) => {
                         panic!("GDB quit!");
                     }
                 }
@@ -139,6 +126,16 @@ impl ExpressionTable {
             row.result.update(&result);
         }
     }
+
+    pub fn add_expression<S: AsRef<str>>(&mut self, expression: S, p: ::UpdateParameters) {
+        {
+            let mut rows = self.table.rows_mut();
+            let last_row = rows.last_mut().expect("At least one row");
+            last_row.expression.set(expression.as_ref());
+        }
+        self.shrink_to_fit();
+        self.update_results(p);
+    }
 }
 
 impl Widget for ExpressionTable {
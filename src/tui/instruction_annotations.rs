@@ -0,0 +1,507 @@
+// Annotations of (x86) instructions with the current values of the registers and memory locations
+// they read.
+use gdbmi::commands::MiCommand;
+use gdbmi::output::ResultClass;
+use std::collections::HashMap;
+
+// Values that are smaller than this are most likely not pointers, so we do not look up symbols.
+const MIN_SYMBOL_ADDRESS: u64 = 0x1000;
+
+const PREFIXES: &[&str] = &[
+    "bnd", "notrack", "lock", "rep", "repe", "repz", "repne", "repnz",
+];
+
+// Instructions that do not read a register destination operand.
+const WRITE_ONLY_DESTINATION: &[&str] = &["mov", "lea", "pop", "set", "cvt"];
+
+#[derive(Debug, PartialEq)]
+struct Instruction<'a> {
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+    att_syntax: bool,
+}
+
+fn split_operands(operands: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut begin = 0;
+    for (i, c) in operands.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(operands[begin..i].trim());
+                begin = i + 1;
+            }
+            _ => {}
+        }
+    }
+    let last = operands[begin..].trim();
+    if !last.is_empty() {
+        result.push(last);
+    }
+    result
+}
+
+fn parse_instruction<'a>(instruction: &'a str) -> Option<Instruction<'a>> {
+    // Strip symbol information ("<main+4>") and comments
+    let mut rest = instruction.split(|c| c == '<' || c == '#').next()?.trim();
+    let mnemonic = loop {
+        let mut split = rest.splitn(2, char::is_whitespace);
+        let word = split.next()?;
+        rest = split.next().unwrap_or("").trim();
+        if !PREFIXES.contains(&word) {
+            break word;
+        }
+    };
+    if mnemonic.is_empty() {
+        return None;
+    }
+    Some(Instruction {
+        mnemonic: mnemonic,
+        operands: split_operands(rest),
+        att_syntax: rest.contains('%'),
+    })
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+// Find all register names in the operand. In AT&T syntax they are prefixed by '%', otherwise we
+// have to check against the list of known registers.
+fn operand_registers<'a>(
+    operand: &'a str,
+    att_syntax: bool,
+    registers: &HashMap<String, usize>,
+) -> Vec<&'a str> {
+    operand
+        .split(|c: char| !is_identifier_char(c) && c != '%')
+        .filter_map(|word| {
+            if att_syntax {
+                if word.starts_with('%') {
+                    Some(&word[1..])
+                } else {
+                    None
+                }
+            } else {
+                Some(word)
+            }
+        })
+        .filter(|word| registers.contains_key(*word))
+        .collect()
+}
+
+fn is_memory_operand(operand: &str, att_syntax: bool) -> bool {
+    if att_syntax {
+        operand.contains('(')
+    } else {
+        operand.contains('[')
+    }
+}
+
+fn read_registers<'a>(
+    instruction: &Instruction<'a>,
+    registers: &HashMap<String, usize>,
+) -> Vec<&'a str> {
+    let destination = if instruction.att_syntax {
+        instruction.operands.len().checked_sub(1)
+    } else if instruction.operands.is_empty() {
+        None
+    } else {
+        Some(0)
+    };
+    let write_only_destination = instruction.operands.len() > 1
+        && WRITE_ONLY_DESTINATION
+            .iter()
+            .any(|m| instruction.mnemonic.starts_with(m));
+
+    let mut result = Vec::new();
+    for (i, operand) in instruction.operands.iter().enumerate() {
+        if Some(i) == destination
+            && write_only_destination
+            && !is_memory_operand(operand, instruction.att_syntax)
+        {
+            continue;
+        }
+        for register in operand_registers(operand, instruction.att_syntax, registers) {
+            if !result.contains(&register) {
+                result.push(register);
+            }
+        }
+    }
+    result
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (negative, s) = if s.starts_with('-') {
+        (true, &s[1..])
+    } else {
+        (false, s)
+    };
+    // Parse as unsigned to allow for (hex) values that do not fit into i64, e.g., 0xffffffffffffffff
+    let value = if s.starts_with("0x") {
+        u64::from_str_radix(&s[2..], 16).ok()? as i64
+    } else {
+        s.parse::<u64>().ok()? as i64
+    };
+    Some(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+// Size in bytes of (x86) general purpose registers.
+fn register_size(name: &str) -> Option<usize> {
+    let bytes = name.as_bytes();
+    match bytes.first()? {
+        b'r' if bytes.len() > 1 && bytes[1].is_ascii_digit() => match bytes[bytes.len() - 1] {
+            b'd' => Some(4),
+            b'w' => Some(2),
+            b'b' | b'l' => Some(1),
+            _ => Some(8),
+        },
+        b'r' => Some(8),
+        b'e' => Some(4),
+        _ if name == "sil" || name == "dil" || name == "spl" || name == "bpl" => Some(1),
+        _ if bytes.len() == 2 && (name.ends_with('l') || name.ends_with('h')) => Some(1),
+        _ if bytes.len() == 2 => Some(2),
+        _ => None,
+    }
+}
+
+fn operand_size(instruction: &Instruction, memory_operand: &str) -> usize {
+    const SIZES: &[(&str, usize)] = &[("BYTE", 1), ("QWORD", 8), ("DWORD", 4), ("WORD", 2)];
+    if !instruction.att_syntax {
+        for &(keyword, size) in SIZES {
+            if memory_operand.starts_with(keyword) {
+                return size;
+            }
+        }
+    }
+    // Otherwise guess from the size of a register operand
+    instruction
+        .operands
+        .iter()
+        .filter(|o| !is_memory_operand(o, instruction.att_syntax))
+        .filter_map(|o| register_size(o.trim_start_matches('%')))
+        .next()
+        .unwrap_or(8)
+}
+
+// Compute the effective address of a memory operand. Returns None for segment or instruction
+// pointer relative addressing (for the latter gdb already prints the address as a comment).
+fn memory_address(operand: &str, att_syntax: bool, values: &HashMap<&str, u64>) -> Option<u64> {
+    if operand.contains(':') || operand.contains("rip") {
+        return None;
+    }
+    let register_value = |name: &str| values.get(name.trim().trim_start_matches('%')).cloned();
+    let address = if att_syntax {
+        // disp(base,index,scale)
+        let open = operand.find('(')?;
+        let close = operand.rfind(')')?;
+        let displacement = if operand[..open].is_empty() {
+            0
+        } else {
+            parse_number(&operand[..open])?
+        };
+        let mut parts = operand[open + 1..close].split(',');
+        let base = match parts.next().map(|b| b.trim()) {
+            Some(b) if !b.is_empty() => register_value(b)?,
+            _ => 0,
+        };
+        let index = match parts.next() {
+            Some(i) => register_value(i)?,
+            None => 0,
+        };
+        let scale = match parts.next() {
+            Some(s) => parse_number(s)? as u64,
+            None => 1,
+        };
+        base.wrapping_add(index.wrapping_mul(scale))
+            .wrapping_add(displacement as u64)
+    } else {
+        // [base+index*scale+disp]
+        let open = operand.find('[')?;
+        let close = operand.rfind(']')?;
+        let mut address = 0u64;
+        let mut term_begin = open + 1;
+        let mut negative = false;
+        for (i, c) in operand[open + 1..=close].char_indices() {
+            let i = i + open + 1;
+            if c != '+' && c != '-' && c != ']' {
+                continue;
+            }
+            let term = operand[term_begin..i].trim();
+            if !term.is_empty() {
+                let value = if let Some(star) = term.find('*') {
+                    register_value(&term[..star])?
+                        .wrapping_mul(parse_number(&term[star + 1..])? as u64)
+                } else if let Some(value) = register_value(term) {
+                    value
+                } else {
+                    parse_number(term)? as u64
+                };
+                address = if negative {
+                    address.wrapping_sub(value)
+                } else {
+                    address.wrapping_add(value)
+                };
+            }
+            negative = c == '-';
+            term_begin = i + 1;
+        }
+        address
+    };
+    Some(address)
+}
+
+fn evaluate(expression: String, p: ::UpdateParameters) -> Option<String> {
+    match p
+        .gdb
+        .mi
+        .execute(MiCommand::data_evaluate_expression(expression))
+    {
+        Ok(result) => {
+            if result.class == ResultClass::Done {
+                result.results["value"].as_str().map(|s| s.to_owned())
+            } else {
+                None
+            }
+        }
+        Err(_) => None,
+    }
+}
+
+fn symbol_for(address: u64, p: ::UpdateParameters) -> Option<String> {
+    if address < MIN_SYMBOL_ADDRESS {
+        return None;
+    }
+    // Results look like "(void *) 0x401136 <main+16>"
+    let value = evaluate(format!("(void *) {:#x}", address), p)?;
+    let begin = value.find('<')?;
+    let end = value.rfind('>')?;
+    if begin < end {
+        Some(value[begin..=end].to_owned())
+    } else {
+        None
+    }
+}
+
+fn read_memory(address: u64, size: usize, p: ::UpdateParameters) -> Option<u64> {
+    let c_type = match size {
+        1 => "unsigned char",
+        2 => "unsigned short",
+        4 => "unsigned int",
+        _ => "unsigned long",
+    };
+    let value = evaluate(format!("(unsigned long) *({} *) {:#x}", c_type, address), p)?;
+    value.split_whitespace().next()?.parse::<u64>().ok()
+}
+
+fn format_value(value: u64, p: ::UpdateParameters) -> String {
+    match symbol_for(value, p) {
+        Some(symbol) => format!("{:#x} {}", value, symbol),
+        None => format!("{:#x}", value),
+    }
+}
+
+pub struct RegisterNames {
+    numbers: HashMap<String, usize>,
+}
+
+impl RegisterNames {
+    pub fn query(p: ::UpdateParameters) -> Option<Self> {
+        let result = p
+            .gdb
+            .mi
+            .execute(MiCommand::data_list_register_names())
+            .ok()?;
+        if result.class != ResultClass::Done {
+            return None;
+        }
+        let numbers = result.results["register-names"]
+            .members()
+            .enumerate()
+            .filter_map(|(i, name)| match name.as_str() {
+                Some(name) if !name.is_empty() => Some((name.to_owned(), i)),
+                _ => None,
+            })
+            .collect();
+        Some(RegisterNames { numbers: numbers })
+    }
+}
+
+// Describe the values of registers and memory locations read by the given instructions. The
+// result contains one (possibly empty) annotation per instruction.
+pub fn annotate_instructions(
+    instructions: &[&str],
+    registers: &RegisterNames,
+    p: ::UpdateParameters,
+) -> Vec<String> {
+    let parsed = instructions
+        .iter()
+        .map(|i| parse_instruction(i))
+        .collect::<Vec<_>>();
+    let mut register_numbers = parsed
+        .iter()
+        .filter_map(|i| i.as_ref())
+        .flat_map(|i| {
+            i.operands
+                .iter()
+                .flat_map(|o| operand_registers(o, i.att_syntax, &registers.numbers))
+                .collect::<Vec<_>>()
+        })
+        .filter_map(|r| registers.numbers.get(r).cloned())
+        .collect::<Vec<_>>();
+    register_numbers.sort();
+    register_numbers.dedup();
+
+    let mut values = HashMap::new();
+    if !register_numbers.is_empty() {
+        if let Ok(result) = p
+            .gdb
+            .mi
+            .execute(MiCommand::data_list_register_values(&register_numbers))
+        {
+            let names_by_number = registers
+                .numbers
+                .iter()
+                .map(|(name, &number)| (number, name.as_str()))
+                .collect::<HashMap<_, _>>();
+            for value in result.results["register-values"].members() {
+                let number = value["number"]
+                    .as_str()
+                    .and_then(|n| n.parse::<usize>().ok());
+                let name = number.and_then(|n| names_by_number.get(&n));
+                // Vector registers (for example) have structured values, which we ignore.
+                let value = value["value"].as_str().and_then(parse_number);
+                if let (Some(name), Some(value)) = (name, value) {
+                    values.insert(*name, value as u64);
+                }
+            }
+        }
+    }
+
+    parsed
+        .iter()
+        .map(|instruction| {
+            let instruction = if let &Some(ref instruction) = instruction {
+                instruction
+            } else {
+                return String::new();
+            };
+            let mut parts = Vec::new();
+            for register in read_registers(instruction, &registers.numbers) {
+                if let Some(&value) = values.get(register) {
+                    parts.push(format!("{}={}", register, format_value(value, p)));
+                }
+            }
+            for operand in &instruction.operands {
+                if !is_memory_operand(operand, instruction.att_syntax) {
+                    continue;
+                }
+                if let Some(address) = memory_address(operand, instruction.att_syntax, &values) {
+                    let size = operand_size(instruction, operand);
+                    match read_memory(address, size, p) {
+                        Some(value) => {
+                            parts.push(format!("[{:#x}]={}", address, format_value(value, p)))
+                        }
+                        None => parts.push(format!("[{:#x}]=?", address)),
+                    }
+                }
+            }
+            parts.join(", ")
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn registers() -> HashMap<String, usize> {
+        ["rax", "rbx", "rbp", "rsp", "rdi", "eax", "edx", "al"]
+            .iter()
+            .enumerate()
+            .map(|(i, r)| (r.to_string(), i))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_instruction() {
+        assert_eq!(
+            parse_instruction("mov    -0x14(%rbp,%rax,4),%edx"),
+            Some(Instruction {
+                mnemonic: "mov",
+                operands: vec!["-0x14(%rbp,%rax,4)", "%edx"],
+                att_syntax: true,
+            })
+        );
+        assert_eq!(
+            parse_instruction("lock cmpxchg DWORD PTR [rdi],edx"),
+            Some(Instruction {
+                mnemonic: "cmpxchg",
+                operands: vec!["DWORD PTR [rdi]", "edx"],
+                att_syntax: false,
+            })
+        );
+        assert_eq!(
+            parse_instruction("call   0x401030 <puts@plt>"),
+            Some(Instruction {
+                mnemonic: "call",
+                operands: vec!["0x401030"],
+                att_syntax: false,
+            })
+        );
+        assert_eq!(
+            parse_instruction("ret    "),
+            Some(Instruction {
+                mnemonic: "ret",
+                operands: vec![],
+                att_syntax: false,
+            })
+        );
+    }
+
+    #[test]
+    fn test_read_registers() {
+        let registers = registers();
+        fn read<'a>(instruction: &'a str, registers: &HashMap<String, usize>) -> Vec<&'a str> {
+            read_registers(&parse_instruction(instruction).unwrap(), registers)
+        }
+        assert_eq!(
+            read("mov    -0x14(%rbp,%rax,4),%edx", &registers),
+            vec!["rbp", "rax"]
+        );
+        assert_eq!(read("add    %eax,%edx", &registers), vec!["eax", "edx"]);
+        assert_eq!(read("mov    %eax,(%rbx)", &registers), vec!["eax", "rbx"]);
+        assert_eq!(
+            read("mov    edx,DWORD PTR [rbp-0x14]", &registers),
+            vec!["rbp"]
+        );
+        assert_eq!(read("push   rbp", &registers), vec!["rbp"]);
+    }
+
+    #[test]
+    fn test_memory_address() {
+        let mut values = HashMap::new();
+        values.insert("rbp", 0x1000);
+        values.insert("rax", 2);
+        assert_eq!(
+            memory_address("-0x14(%rbp,%rax,4)", true, &values),
+            Some(0x1000 - 0x14 + 8)
+        );
+        assert_eq!(memory_address("(%rax)", true, &values), Some(2));
+        assert_eq!(memory_address("0x8(,%rax,8)", true, &values), Some(0x18));
+        assert_eq!(
+            memory_address("DWORD PTR [rbp+rax*4-0x14]", false, &values),
+            Some(0x1000 + 8 - 0x14)
+        );
+        assert_eq!(memory_address("%fs:0x28", true, &values), None);
+        assert_eq!(memory_address("0x2edb(%rip)", true, &values), None);
+        assert_eq!(memory_address("(%rbx)", true, &values), None);
+    }
+}
@@ -1,6 +1,8 @@
 pub mod commands;
 pub mod console;
 pub mod expression_table;
+pub mod instruction_annotations;
+pub mod process_picker;
 pub mod srcview;
 pub mod tui;
 
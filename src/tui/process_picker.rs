@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use unsegen::base::{Cursor, StyleModifier, Window};
+use unsegen::widget::{Demand, Demand2D, RenderingHints, Widget};
+
+pub struct Process {
+    pub pid: u32,
+    parent_pid: u32,
+    // Name of the executable
+    pub name: String,
+    pub user: String,
+    pub command_line: String,
+    // In clock ticks after system boot
+    start_time: u64,
+}
+
+impl Process {
+    // All whitespace separated terms of the filter have to match the pid, user, name or command
+    // line (case insensitive).
+    fn matches_filter(&self, filter: &str) -> bool {
+        let pid = self.pid.to_string();
+        filter.split_whitespace().all(|term| {
+            let term = term.to_lowercase();
+            term == pid
+                || [&self.user, &self.name, &self.command_line]
+                    .iter()
+                    .any(|s| s.to_lowercase().contains(&term))
+        })
+    }
+
+    // Unlike the filter, the pattern is not matched against the command line, where it would also
+    // be found in the arguments of, e.g., the shell that started ugdb.
+    fn matches_name(&self, pattern: &str) -> bool {
+        self.name.contains(pattern)
+    }
+}
+
+fn read_user_names() -> HashMap<u32, String> {
+    fs::read_to_string("/etc/passwd")
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse::<u32>().ok()?;
+            Some((uid, name.to_owned()))
+        })
+        .collect()
+}
+
+fn read_process(dir: &Path, pid: u32, user_names: &HashMap<u32, String>) -> Option<Process> {
+    let command_line = fs::read(dir.join("cmdline"))
+        .ok()?
+        .split(|&b| b == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(|arg| String::from_utf8_lossy(arg).into_owned())
+        .collect::<Vec<_>>()
+        .join(" ");
+    // Kernel threads do not have a command line and cannot be debugged anyway.
+    if command_line.is_empty() {
+        return None;
+    }
+    // comm is truncated to 15 characters, but exe is only readable for our own processes.
+    let name = match fs::read_link(dir.join("exe")) {
+        Ok(exe) => exe.file_name()?.to_string_lossy().into_owned(),
+        Err(_) => fs::read_to_string(dir.join("comm"))
+            .ok()?
+            .trim_end()
+            .to_owned(),
+    };
+    let uid = fs::read_to_string(dir.join("status"))
+        .ok()?
+        .lines()
+        .find(|line| line.starts_with("Uid:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse::<u32>()
+        .ok()?;
+    let stat = fs::read_to_string(dir.join("stat")).ok()?;
+    // The process name (second field) may contain spaces and parentheses, so we only look at the
+    // fields after it. The parent pid is the 4th and the start time is the 22nd field.
+    let fields = stat[stat.rfind(')')? + 1..]
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    let parent_pid = fields.get(1)?.parse::<u32>().ok()?;
+    let start_time = fields.get(19)?.parse::<u64>().ok()?;
+    Some(Process {
+        pid: pid,
+        parent_pid: parent_pid,
+        name: name,
+        user: user_names
+            .get(&uid)
+            .cloned()
+            .unwrap_or_else(|| uid.to_string()),
+        command_line: command_line,
+        start_time: start_time,
+    })
+}
+
+// Newest processes first, excluding ugdb itself.
+pub fn list_processes() -> Vec<Process> {
+    let user_names = read_user_names();
+    let own_pid = ::std::process::id();
+    let mut processes = fs::read_dir("/proc")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| {
+                    let entry = entry.ok()?;
+                    let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+                    if pid == own_pid {
+                        return None;
+                    }
+                    read_process(&entry.path(), pid, &user_names)
+                })
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    processes.sort_by(|a, b| b.start_time.cmp(&a.start_time));
+    processes
+}
+
+// Excludes the ancestors of ugdb (e.g., a script that starts ugdb with a pattern that matches its
+// own name).
+pub fn newest_process_matching(pattern: &str) -> Option<Process> {
+    let processes = list_processes();
+    let parents = processes
+        .iter()
+        .map(|process| (process.pid, process.parent_pid))
+        .collect::<HashMap<_, _>>();
+    let mut ancestors = HashSet::new();
+    let mut ancestor = ::std::os::unix::process::parent_id();
+    while ancestor != 0 && ancestors.insert(ancestor) {
+        ancestor = parents.get(&ancestor).cloned().unwrap_or(0);
+    }
+    processes
+        .into_iter()
+        .find(|process| !ancestors.contains(&process.pid) && process.matches_name(pattern))
+}
+
+pub struct ProcessPicker {
+    processes: Vec<Process>,
+    filter: String,
+    selected: usize,
+}
+
+impl ProcessPicker {
+    pub fn new() -> Self {
+        ProcessPicker {
+            processes: list_processes(),
+            filter: String::new(),
+            selected: 0,
+        }
+    }
+
+    fn matching_processes<'a>(&'a self) -> impl Iterator<Item = &'a Process> + 'a {
+        self.processes
+            .iter()
+            .filter(move |process| process.matches_filter(&self.filter))
+    }
+
+    pub fn set_filter(&mut self, filter: &str) {
+        if self.filter != filter {
+            self.filter = filter.to_owned();
+            self.selected = 0;
+        }
+    }
+
+    pub fn select_next(&mut self) {
+        if self.selected + 1 < self.matching_processes().count() {
+            self.selected += 1;
+        }
+    }
+
+    pub fn select_previous(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    pub fn selected_process(&self) -> Option<&Process> {
+        self.matching_processes().nth(self.selected)
+    }
+}
+
+impl Widget for ProcessPicker {
+    fn space_demand(&self) -> Demand2D {
+        Demand2D {
+            width: Demand::at_least(1),
+            height: Demand::at_least(1),
+        }
+    }
+    fn draw(&self, mut window: Window, _: RenderingHints) {
+        use std::fmt::Write;
+        let height = window.get_height().raw_value().max(1) as usize;
+        let mut cursor = Cursor::new(&mut window);
+        {
+            let mut cursor = cursor.save().style_modifier();
+            cursor.set_style_modifier(StyleModifier::new().bold(true));
+            let _ = writeln!(cursor, "{:>7} {:<10} COMMAND", "PID", "USER");
+        }
+        // Keep the selected process in view
+        let num_rows = height - 1;
+        let first_row = (self.selected + 1).saturating_sub(num_rows);
+        for (i, process) in self
+            .matching_processes()
+            .enumerate()
+            .skip(first_row)
+            .take(num_rows)
+        {
+            let mut cursor = cursor.save().style_modifier();
+            if i == self.selected {
+                cursor.set_style_modifier(StyleModifier::new().invert(true));
+            }
+            let _ = writeln!(
+                cursor,
+                "{:>7} {:<10} {}",
+                process.pid, process.user, process.command_line
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_matches_filter() {
+        let process = Process {
+            pid: 1234,
+            parent_pid: 1,
+            name: "server".to_owned(),
+            user: "alice".to_owned(),
+            command_line: "./server --port 8080".to_owned(),
+            start_time: 0,
+        };
+        assert!(process.matches_filter(""));
+        assert!(process.matches_filter("1234"));
+        assert!(process.matches_filter("Alice SERVER"));
+        assert!(process.matches_filter("8080"));
+        assert!(!process.matches_filter("123"));
+        assert!(!process.matches_filter("server bob"));
+        assert!(process.matches_name("serv"));
+        assert!(!process.matches_name("alice"));
+        assert!(!process.matches_name("8080"));
+    }
+}
@@ -1,25 +1,36 @@
-use gdb::{response::*, Address, BreakPoint, BreakpointOperationError, SrcPosition};
-use gdbmi::commands::{BreakPointLocation, BreakPointNumber, DisassembleMode, MiCommand};
-use gdbmi::output::{JsonValue, Object, ResultClass};
+use super::instruction_annotations::{annotate_instructions, RegisterNames};
+use annotations::{AnnotationSets, Severity};
+use gdb::{
+    response::*, Address, BreakPoint, BreakpointOperationError, EvaluationError, RecordState,
+    SrcPosition,
+};
+use gdbmi::commands::{
+    BreakPointLocation, BreakPointNumber, DisassembleMode, MiCommand, ValuePrintMode,
+};
+use gdbmi::output::{JsonValue, Object, ResultClass, ResultRecord};
 use gdbmi::ExecuteError;
 use log::warn;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
+use syntect::parsing::{ParseState, Scope, ScopeStack};
 use unsegen::base::basic_types::*;
-use unsegen::base::{Color, Cursor, GraphemeCluster, StyleModifier, Window};
+use unsegen::base::{BoolModifyMode, Color, Cursor, GraphemeCluster, StyleModifier, Window};
 use unsegen::container::Container;
-use unsegen::input::{Input, Key, ScrollBehavior};
+use unsegen::input::{Input, Key, ScrollBehavior, Scrollable};
 use unsegen::widget::{
     text_width, ColDemand, Demand, Demand2D, HorizontalLayout, RenderingHints, SeparatingStyle,
     Widget,
 };
+use unsegen_jsonviewer::JsonViewer;
 use unsegen_pager::{
-    LineDecorator, Pager, PagerContent, PagerError, PagerLine, SyntectHighlighter,
+    HighlightInfo, Highlighter, LineDecorator, Pager, PagerContent, PagerError, PagerLine,
+    SyntectHighlighter,
 };
 use unsegen_pager::{SyntaxSet, Theme};
+use UIRequest;
 
 #[derive(Debug)]
 pub enum PagerShowError {
@@ -43,12 +54,132 @@ impl AssemblyDebugLocation {
     }
 }
 
+// Instructions with a direct (i.e., not register or memory based) jump target.
+fn is_branch_mnemonic(mnemonic: &str) -> bool {
+    // x86
+    mnemonic.starts_with('j')
+        || mnemonic.starts_with("call")
+        || mnemonic.starts_with("loop")
+        // arm and aarch64
+        || mnemonic == "b"
+        || mnemonic.starts_with("b.")
+        || mnemonic == "bl"
+        || mnemonic == "blx"
+        || mnemonic.starts_with("cbz")
+        || mnemonic.starts_with("cbnz")
+        || mnemonic.starts_with("tbz")
+        || mnemonic.starts_with("tbnz")
+}
+
+fn branch_target(instruction: &str) -> Option<Address> {
+    // Strip symbol information ("<main+4>") and comments
+    let instruction = instruction.split(|c| c == '<' || c == '#').next()?;
+    let mut tokens = instruction.split_whitespace();
+    let mut mnemonic = tokens.next()?;
+    while mnemonic == "bnd" || mnemonic == "notrack" {
+        mnemonic = tokens.next()?;
+    }
+    if !is_branch_mnemonic(mnemonic) {
+        return None;
+    }
+    tokens
+        .map(|t| t.trim_matches(','))
+        .filter(|t| t.starts_with("0x") && t.len() > 2)
+        .next()
+        .and_then(|t| Address::parse(t).ok())
+}
+
+const MAX_JUMP_ARROW_LANES: usize = 6;
+
+// Draw ascii arrows for all jumps between the given lines. The result contains one string (of
+// equal width) per line.
+fn jump_arrows(lines: &[AssemblyLine]) -> Vec<String> {
+    let line_of_address = lines
+        .iter()
+        .enumerate()
+        .filter(|&(_, l)| !l.source_header)
+        .map(|(i, l)| (l.address, i))
+        .collect::<HashMap<_, _>>();
+    let mut jumps = lines
+        .iter()
+        .enumerate()
+        .filter_map(|(from, l)| {
+            let to = *line_of_address.get(&l.branch_target?)?;
+            if from != to {
+                Some((from, to))
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+    // Short jumps are placed closest to the instructions.
+    jumps.sort_by_key(|&(from, to)| (from as isize - to as isize).abs());
+
+    let mut lanes: Vec<Vec<(usize, usize)>> = Vec::new();
+    let mut placed_jumps = Vec::new();
+    for (from, to) in jumps {
+        let range = (from.min(to), from.max(to));
+        let free_lane = lanes
+            .iter()
+            .position(|lane| lane.iter().all(|&(b, e)| range.1 < b || e < range.0));
+        let lane = match free_lane {
+            Some(lane) => lane,
+            None if lanes.len() < MAX_JUMP_ARROW_LANES => {
+                lanes.push(Vec::new());
+                lanes.len() - 1
+            }
+            None => continue,
+        };
+        lanes[lane].push(range);
+        placed_jumps.push((from, to, lane));
+    }
+
+    if lanes.is_empty() {
+        return vec![String::new(); lines.len()];
+    }
+    let width = lanes.len() + 1;
+    let mut grid = vec![vec![' '; width]; lines.len()];
+    for &(from, to, lane) in &placed_jumps {
+        let column = width - 2 - lane;
+        let (begin, end) = (from.min(to), from.max(to));
+        for row in grid.iter_mut().take(end).skip(begin + 1) {
+            row[column] = match row[column] {
+                '-' => '+',
+                _ => '|',
+            };
+        }
+        for &(row, corner) in &[(begin, ','), (end, '`')] {
+            let row = &mut grid[row];
+            row[column] = corner;
+            for cell in row.iter_mut().take(width - 1).skip(column + 1) {
+                *cell = match *cell {
+                    '|' | '+' => '+',
+                    _ => '-',
+                };
+            }
+        }
+        grid[from][width - 1] = match grid[from][width - 1] {
+            '>' => '>',
+            _ => '-',
+        };
+        grid[to][width - 1] = '>';
+    }
+    grid.into_iter()
+        .map(|row| row.into_iter().collect())
+        .collect()
+}
+
 #[derive(Clone)]
 struct AssemblyLine {
     content: String,
     address: Address,
     src_position: Option<SrcPosition>,
     debug_location: Option<AssemblyDebugLocation>,
+    opcodes: Option<String>,
+    branch_target: Option<Address>,
+    jump_arrows: String,
+    // Source lines in interleaved mode share the address of the first following instruction.
+    source_header: bool,
 }
 
 impl AssemblyLine {
@@ -57,12 +188,43 @@ impl AssemblyLine {
         address: Address,
         src_position: Option<SrcPosition>,
         debug_location: Option<AssemblyDebugLocation>,
+        opcodes: Option<String>,
     ) -> Self {
         AssemblyLine {
+            branch_target: branch_target(&content),
             content: content,
             address: address,
             src_position: src_position,
             debug_location: debug_location,
+            opcodes: opcodes,
+            jump_arrows: String::new(),
+            source_header: false,
+        }
+    }
+
+    fn try_from_value(
+        val: &JsonValue,
+        src_position: Option<SrcPosition>,
+    ) -> Result<Self, GDBResponseError> {
+        Ok(AssemblyLine::new(
+            get_str(val, "inst")?.to_owned(),
+            get_addr(val, "address")?,
+            src_position,
+            AssemblyDebugLocation::try_from_value(val),
+            val["opcodes"].as_str().map(|s| s.to_owned()),
+        ))
+    }
+
+    fn source_header(content: String, address: Address, src_position: SrcPosition) -> Self {
+        AssemblyLine {
+            content: content,
+            address: address,
+            src_position: Some(src_position),
+            debug_location: None,
+            opcodes: None,
+            branch_target: None,
+            jump_arrows: String::new(),
+            source_header: true,
         }
     }
 }
@@ -73,21 +235,57 @@ impl PagerLine for AssemblyLine {
     }
 }
 
+// While replaying recorded execution, the replay position is drawn in yellow and the live
+// position of the inferior is marked as well.
+fn position_marker(
+    at_stop_position: bool,
+    at_live_position: bool,
+    at_breakpoint_position: bool,
+    replaying: bool,
+) -> (char, StyleModifier) {
+    match (at_stop_position, at_live_position, at_breakpoint_position) {
+        (true, _, true) => ('▶', StyleModifier::new().fg_color(Color::Red).bold(true)),
+        (true, _, false) => (
+            '▶',
+            StyleModifier::new()
+                .fg_color(if replaying {
+                    Color::Yellow
+                } else {
+                    Color::Green
+                })
+                .bold(true),
+        ),
+        (false, true, true) => ('▷', StyleModifier::new().fg_color(Color::Red)),
+        (false, true, false) => ('▷', StyleModifier::new().fg_color(Color::Green)),
+        (false, false, true) => ('●', StyleModifier::new().fg_color(Color::Red)),
+        (false, false, false) => (' ', StyleModifier::new()),
+    }
+}
+
 struct AssemblyDecorator {
     stop_position: Option<Address>,
+    live_position: Option<Address>,
     breakpoint_addresses: HashSet<Address>,
+    breakpoint_src_positions: HashSet<SrcPosition>,
 }
 
 impl AssemblyDecorator {
     fn new<'a, I: Iterator<Item = &'a BreakPoint>>(
         address_range: Range<Address>,
         stop_position: Option<Address>,
+        live_position: Option<Address>,
         breakpoints: I,
     ) -> Self {
+        let breakpoints = breakpoints.filter(|bp| bp.enabled).collect::<Vec<_>>();
+        let src_positions = breakpoints
+            .iter()
+            .filter_map(|bp| bp.src_pos.clone())
+            .collect();
         let addresses = breakpoints
+            .iter()
             .filter_map(|bp| {
                 bp.address.and_then(|addr| {
-                    if bp.enabled && address_range.start <= addr && addr < address_range.end {
+                    if address_range.start <= addr && addr < address_range.end {
                         Some(addr)
                     } else {
                         None
@@ -106,7 +304,9 @@ impl AssemblyDecorator {
         };
         AssemblyDecorator {
             stop_position: stop_position,
+            live_position: live_position,
             breakpoint_addresses: addresses,
+            breakpoint_src_positions: src_positions,
         }
     }
 }
@@ -119,7 +319,7 @@ impl LineDecorator for AssemblyDecorator {
     ) -> ColDemand {
         let max_space = lines
             .last()
-            .map(|(_, l)| text_width(format!(" 0x{:x} ", l.address.0).as_str()))
+            .map(|(_, l)| text_width(format!(" 0x{:x} {}", l.address.0, l.jump_arrows).as_str()))
             .unwrap_or(Width::new(0).unwrap());
         Demand::from_to(0, max_space.into())
     }
@@ -128,30 +328,60 @@ impl LineDecorator for AssemblyDecorator {
         line: &Self::Line,
         current_line: LineIndex,
         active_line: LineIndex,
-        mut window: Window,
+        window: Window,
     ) {
+        let arrows_column = window.get_width().raw_value() - line.jump_arrows.len() as i32;
+        let mut window = match window.split(ColIndex::new(arrows_column)) {
+            Ok((window, mut arrows_window)) => {
+                Cursor::new(&mut arrows_window)
+                    .style_modifier(StyleModifier::new().fg_color(Color::Cyan))
+                    .write(&line.jump_arrows);
+                window
+            }
+            Err(window) => window,
+        };
         let width = window.get_width();
         let mut cursor = Cursor::new(&mut window).position(ColIndex::new(0), RowIndex::new(0));
 
+        use std::fmt::Write;
+        if line.source_header {
+            let src_position = line
+                .src_position
+                .as_ref()
+                .expect("Source headers always have a position");
+            let right_border = if self.breakpoint_src_positions.contains(src_position) {
+                cursor.set_style_modifier(StyleModifier::new().fg_color(Color::Red));
+                '●'
+            } else {
+                ' '
+            };
+            write!(
+                cursor,
+                "{:>width$}{}",
+                src_position.line,
+                right_border,
+                width = (width - 1).positive_or_zero().into()
+            )
+            .unwrap();
+            return;
+        }
+
         let at_stop_position = self
             .stop_position
             .map(|p| p == line.address)
             .unwrap_or(false);
+        let at_live_position = self.live_position == Some(line.address);
         let at_breakpoint_position = self.breakpoint_addresses.contains(&line.address);
 
-        let (right_border, style_modifier) = match (at_stop_position, at_breakpoint_position) {
-            (true, true) => ('▶', StyleModifier::new().fg_color(Color::Red).bold(true)),
-            (true, false) => (
-                '▶',
-                StyleModifier::new().fg_color(Color::Green).bold(true),
-            ),
-            (false, true) => ('●', StyleModifier::new().fg_color(Color::Red)),
-            (false, false) => (' ', StyleModifier::new()),
-        };
+        let (right_border, style_modifier) = position_marker(
+            at_stop_position,
+            at_live_position,
+            at_breakpoint_position,
+            self.live_position.is_some(),
+        );
 
         cursor.set_style_modifier(style_modifier);
 
-        use std::fmt::Write;
         if let (false, Some(offset)) = (
             current_line == active_line,
             line.debug_location
@@ -182,11 +412,114 @@ impl LineDecorator for AssemblyDecorator {
     }
 }
 
+struct InstructionText<'a>(&'a str);
+
+impl<'a> PagerLine for InstructionText<'a> {
+    fn get_content(&self) -> &str {
+        self.0
+    }
+}
+
+// Highlights instructions as assembly (after the raw opcodes, if any) and source lines (in
+// interleaved mode, i.e., lines without instruction start) as headers.
+struct AssemblyHighlighter<'a> {
+    asm: SyntectHighlighter<'a>,
+    instruction_starts: &'a [Option<usize>],
+    annotation_starts: &'a [Option<usize>],
+}
+
+impl<'a> Highlighter for AssemblyHighlighter<'a> {
+    fn highlight<'b, L: Iterator<Item = &'b dyn PagerLine>>(&self, lines: L) -> HighlightInfo {
+        // Source lines and opcodes must not be fed to the assembly parser, as they could change
+        // its state.
+        let instructions = lines
+            .zip(self.instruction_starts.iter().zip(self.annotation_starts))
+            .filter_map(|(line, (start, annotation_start))| {
+                let content = line.get_content();
+                let end = annotation_start.unwrap_or(content.len());
+                start.map(|start| InstructionText(&content[start..end]))
+            })
+            .collect::<Vec<_>>();
+        let mut info = self
+            .asm
+            .highlight(instructions.iter().map(|l| l as &dyn PagerLine));
+        let mut asm_changes = ::std::mem::replace(&mut info.style_changes, Vec::new()).into_iter();
+        info.style_changes = self
+            .instruction_starts
+            .iter()
+            .zip(self.annotation_starts)
+            .map(|(&start, &annotation_start)| {
+                let mut changes = match start {
+                    None => vec![(0, StyleModifier::new().bold(true))],
+                    Some(0) => asm_changes.next().unwrap_or(Vec::new()),
+                    Some(start) => {
+                        let mut changes =
+                            vec![(0, StyleModifier::new().fg_color(Color::LightBlack))];
+                        changes.extend(
+                            asm_changes
+                                .next()
+                                .unwrap_or(Vec::new())
+                                .into_iter()
+                                .map(|(pos, style)| (pos + start, style)),
+                        );
+                        changes
+                    }
+                };
+                if let Some(annotation_start) = annotation_start {
+                    changes.push((
+                        annotation_start,
+                        StyleModifier::new()
+                            .fg_color(Color::LightBlack)
+                            .italic(true),
+                    ));
+                }
+                changes
+            })
+            .collect();
+        info
+    }
+}
+
+// Specifies what has been disassembled so that we are able to reload the same range.
+#[derive(Clone, PartialEq)]
+enum AsmContent {
+    File(PathBuf, LineNumber),
+    Addresses(Address, Address),
+}
+
+// Number of bytes that are disassembled when scrolling beyond the loaded instructions.
+const ASM_LOAD_BLOCK_SIZE: usize = 128;
+const ASM_BACKWARDS_ALIGNMENT_ATTEMPTS: usize = 16;
+
+// Number of instructions after the stop position that are annotated with register values (if
+// enabled).
+const REGISTER_ANNOTATION_FOLLOWING_LINES: usize = 4;
+const REGISTER_ANNOTATION_MAX_WIDTH: usize = 100;
+
+#[derive(Clone, Copy, PartialEq)]
+enum RegisterAnnotationMode {
+    Off,
+    StopPosition,
+    StopPositionAndFollowing,
+}
+
 pub struct AssemblyView<'a> {
     highlighting_theme: &'a Theme,
     syntax_set: SyntaxSet,
     pager: Pager<AssemblyLine, AssemblyDecorator>,
     last_stop_position: Option<Address>,
+    // Only present while replaying recorded execution
+    live_position: Option<Address>,
+    instructions: Vec<AssemblyLine>,
+    interleaved: bool,
+    show_opcodes: bool,
+    loaded_content: Option<AsmContent>,
+    // Range of all instructions if more than `loaded_content` has been loaded by scrolling past
+    // its edges.
+    extended_range: Option<(Address, Address)>,
+    jump_history: Vec<(Option<AsmContent>, Address)>,
+    register_annotation_mode: RegisterAnnotationMode,
+    register_annotations: HashMap<Address, String>,
 }
 
 #[derive(Debug, From)]
@@ -203,14 +536,29 @@ impl<'a> AssemblyView<'a> {
             syntax_set: SyntaxSet::load_defaults_nonewlines(),
             pager: Pager::new(),
             last_stop_position: None,
+            live_position: None,
+            instructions: Vec::new(),
+            interleaved: false,
+            show_opcodes: false,
+            loaded_content: None,
+            extended_range: None,
+            jump_history: Vec::new(),
+            register_annotation_mode: RegisterAnnotationMode::StopPosition,
+            register_annotations: HashMap::new(),
         }
     }
     fn set_last_stop_position(&mut self, pos: Address) {
         self.last_stop_position = Some(pos);
     }
 
+    fn set_live_position(&mut self, pos: Option<Address>) {
+        self.live_position = pos;
+    }
+
     fn go_to_address(&mut self, pos: Address) -> Result<(), GotoError> {
-        Ok(self.pager.go_to_line_if(|_, line| line.address == pos)?)
+        Ok(self
+            .pager
+            .go_to_line_if(|_, line| !line.source_header && line.address == pos)?)
     }
 
     fn go_to_first_applicable_line<L: Into<LineNumber>>(
@@ -251,6 +599,7 @@ impl<'a> AssemblyView<'a> {
                 content.set_decorator(AssemblyDecorator::new(
                     min_address..max_address,
                     self.last_stop_position,
+                    self.live_position,
                     p.gdb.breakpoints.values(),
                 ));
             }
@@ -261,9 +610,125 @@ impl<'a> AssemblyView<'a> {
         if lines.is_empty() {
             return; //Nothing to show
         }
-        let min_address = lines.first().expect("We know lines is not empty").address;
+        self.instructions = lines;
+        self.refresh_content(p);
+    }
+
+    fn set_interleaved(&mut self, interleaved: bool, p: ::UpdateParameters) {
+        if self.interleaved != interleaved {
+            self.interleaved = interleaved;
+            let current_address = self.pager.current_line().map(|l| l.address);
+            self.refresh_content(p);
+            if let Some(address) = current_address {
+                let _ = self.go_to_address(address);
+            }
+        }
+    }
+
+    // Insert the source line before the first instruction of each consecutive group of
+    // instructions that belong to it.
+    fn interleave_source_lines(instructions: &[AssemblyLine]) -> Vec<AssemblyLine> {
+        let mut file_contents = HashMap::<PathBuf, Option<Vec<String>>>::new();
+        let mut lines = Vec::new();
+        let mut previous_position = None;
+        for instruction in instructions {
+            if let Some(ref src_position) = instruction.src_position {
+                if previous_position != Some(src_position) {
+                    let content = file_contents
+                        .entry(src_position.file.clone())
+                        .or_insert_with(|| {
+                            fs::read_to_string(&src_position.file)
+                                .ok()
+                                .map(|c| c.lines().map(|l| l.to_owned()).collect())
+                        })
+                        .as_ref()
+                        .and_then(|file_lines| {
+                            file_lines.get(LineIndex::from(src_position.line).raw_value())
+                        })
+                        .cloned()
+                        .unwrap_or_else(|| {
+                            format!(
+                                "{}:{}",
+                                src_position.file.to_string_lossy(),
+                                src_position.line
+                            )
+                        });
+                    lines.push(AssemblyLine::source_header(
+                        content,
+                        instruction.address,
+                        src_position.clone(),
+                    ));
+                }
+            }
+            previous_position = instruction.src_position.as_ref();
+            lines.push(instruction.clone());
+        }
+        lines
+    }
+
+    fn refresh_content(&mut self, p: ::UpdateParameters) {
+        if self.instructions.is_empty() {
+            return;
+        }
+        let min_address = self
+            .instructions
+            .first()
+            .expect("We know instructions is not empty")
+            .address;
         //TODO: use RangeInclusive when available on stable
-        let max_address = lines.last().expect("We know lines is not empty").address + 1;
+        let max_address = self
+            .instructions
+            .last()
+            .expect("We know instructions is not empty")
+            .address
+            + 1;
+
+        let mut lines = if self.interleaved {
+            Self::interleave_source_lines(&self.instructions)
+        } else {
+            self.instructions.clone()
+        };
+        let arrows = jump_arrows(&lines);
+        for (line, arrows) in lines.iter_mut().zip(arrows) {
+            line.jump_arrows = arrows;
+        }
+        let opcodes_width = lines
+            .iter()
+            .filter_map(|l| l.opcodes.as_ref().map(|o| o.len()))
+            .max();
+        let instruction_starts = lines
+            .iter_mut()
+            .map(|line| {
+                if line.source_header {
+                    None
+                } else if let Some(width) = opcodes_width {
+                    line.content = format!(
+                        "{:<width$}  {}",
+                        line.opcodes.as_ref().map(|o| o.as_str()).unwrap_or(""),
+                        line.content,
+                        width = width
+                    );
+                    Some(width + 2)
+                } else {
+                    Some(0)
+                }
+            })
+            .collect::<Vec<_>>();
+        let annotation_starts = lines
+            .iter_mut()
+            .map(|line| match self.register_annotations.get(&line.address) {
+                Some(annotation) if !line.source_header => {
+                    let start = line.content.len();
+                    line.content = format!(
+                        "{}  {}",
+                        line.content,
+                        truncate_with_ellipsis(annotation, REGISTER_ANNOTATION_MAX_WIDTH)
+                    );
+                    Some(start)
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
         let syntax = self
             .syntax_set
@@ -271,10 +736,15 @@ impl<'a> AssemblyView<'a> {
             .unwrap_or(self.syntax_set.find_syntax_plain_text());
         self.pager.load(
             PagerContent::from_lines(lines)
-                .with_highlighter(&SyntectHighlighter::new(syntax, self.highlighting_theme))
+                .with_highlighter(&AssemblyHighlighter {
+                    asm: SyntectHighlighter::new(syntax, self.highlighting_theme),
+                    instruction_starts: &instruction_starts,
+                    annotation_starts: &annotation_starts,
+                })
                 .with_decorator(AssemblyDecorator::new(
                     min_address..max_address,
                     self.last_stop_position,
+                    self.live_position,
                     p.gdb.breakpoints.values(),
                 )),
         );
@@ -293,14 +763,7 @@ impl<'a> AssemblyView<'a> {
                 let file = get_str(&line_obj, "fullname")?;
                 let src_pos = Some(SrcPosition::new(PathBuf::from(file), line));
                 for tuple in line_obj["line_asm_insn"].members() {
-                    let instruction = get_str(tuple, "inst")?;
-                    let address = get_addr(tuple, "address")?;
-                    lines.push(AssemblyLine::new(
-                        instruction.to_owned(),
-                        address,
-                        src_pos.clone(),
-                        AssemblyDebugLocation::try_from_value(tuple),
-                    ));
+                    lines.push(AssemblyLine::try_from_value(tuple, src_pos.clone())?);
                 }
             }
             lines.sort_by_key(|l| l.address);
@@ -319,20 +782,27 @@ impl<'a> AssemblyView<'a> {
         line: L,
         p: ::UpdateParameters,
     ) -> Result<(), DisassembleError> {
-        let line_u: usize = line.into().into();
+        let line = line.into();
+        let mode = if self.show_opcodes {
+            DisassembleMode::MixedSourceAndDisassemblyWithRawOpcodes
+        } else {
+            DisassembleMode::MixedSourceAndDisassembly
+        };
         let disass_results = p
             .gdb
             .mi
             .execute(MiCommand::data_disassemble_file(
                 file.as_ref(),
-                line_u,
+                line.into(),
                 None,
-                DisassembleMode::MixedSourceAndDisassembly,
+                mode,
             ))?
             .results;
 
         let lines = Self::get_instructions(&disass_results)?;
         self.show_lines(lines, p);
+        self.loaded_content = Some(AsmContent::File(file.as_ref().to_owned(), line));
+        self.extended_range = None;
         Ok(())
     }
 
@@ -342,23 +812,371 @@ impl<'a> AssemblyView<'a> {
         address_end: Address,
         p: ::UpdateParameters,
     ) -> Result<(), DisassembleError> {
-        let line_objs = disassemble_address(address_start, address_end, p)?;
+        let lines = self.disassemble_range(address_start, address_end, p)?;
+        self.show_lines(lines, p);
+        self.loaded_content = Some(AsmContent::Addresses(address_start, address_end));
+        self.extended_range = None;
+        Ok(())
+    }
+
+    fn disassemble_range(
+        &self,
+        address_start: Address,
+        address_end: Address,
+        p: ::UpdateParameters,
+    ) -> Result<Vec<AssemblyLine>, DisassembleError> {
+        let mode = if self.show_opcodes {
+            DisassembleMode::DisassemblyWithRawOpcodes
+        } else {
+            DisassembleMode::DisassemblyOnly
+        };
+        let line_objs = disassemble_address(address_start, address_end, mode, p)?;
 
         let mut lines = Vec::<AssemblyLine>::new();
         for line_tuple in line_objs {
-            let instruction = get_str(&line_tuple, "inst")?;
-            let address = get_addr(&line_tuple, "address")?;
-            lines.push(AssemblyLine::new(
-                instruction.to_owned(),
-                address,
-                None,
-                AssemblyDebugLocation::try_from_value(&line_tuple),
-            ));
+            lines.push(AssemblyLine::try_from_value(&line_tuple, None)?);
+        }
+        Ok(lines)
+    }
+
+    // Show the (extended) instructions while staying at the current instruction.
+    fn show_extended_instructions(&mut self, p: ::UpdateParameters) {
+        let current_address = self.pager.current_line().map(|l| l.address);
+        if let (Some(first), Some(last)) = (self.instructions.first(), self.instructions.last()) {
+            self.extended_range = Some((first.address, last.address + 1));
+        }
+        self.refresh_content(p);
+        if let Some(address) = current_address {
+            let _ = self.go_to_address(address);
+        }
+    }
+
+    fn load_following_instructions(&mut self, p: ::UpdateParameters) -> bool {
+        let last = if let Some(line) = self.instructions.last() {
+            line.address
+        } else {
+            return false;
+        };
+        let new_lines = match self.disassemble_range(last, last + ASM_LOAD_BLOCK_SIZE, p) {
+            Ok(lines) => lines
+                .into_iter()
+                .filter(|l| l.address > last)
+                .collect::<Vec<_>>(),
+            Err(_) => return false,
+        };
+        if new_lines.is_empty() {
+            return false;
+        }
+        self.instructions.extend(new_lines);
+        self.show_extended_instructions(p);
+        true
+    }
+
+    fn load_preceding_instructions(&mut self, p: ::UpdateParameters) -> bool {
+        let (first, first_offset) = if let Some(line) = self.instructions.first() {
+            (
+                line.address,
+                line.debug_location.as_ref().map(|l| l.offset).unwrap_or(0),
+            )
+        } else {
+            return false;
+        };
+        let lowest_start = first.0.saturating_sub(ASM_LOAD_BLOCK_SIZE);
+        if lowest_start == first.0 {
+            return false;
+        }
+
+        // Instructions (on x86) have variable length, so we do not know where the preceding
+        // instructions start. The start of the current or the preceding function is a safe bet,
+        // otherwise we try a number of start addresses until the disassembly lines up with the
+        // first known instruction.
+        let mut candidates = Vec::new();
+        if 0 < first_offset && first_offset <= ASM_LOAD_BLOCK_SIZE {
+            candidates.push(first - first_offset);
+        }
+        if let Some(start) = Self::function_start_before(first, p) {
+            candidates.push(start);
+        }
+        candidates.extend(
+            (lowest_start..first.0)
+                .take(ASM_BACKWARDS_ALIGNMENT_ATTEMPTS)
+                .map(Address),
+        );
+        for candidate in candidates {
+            let lines = match self.disassemble_range(candidate, first + 1, p) {
+                Ok(lines) => lines,
+                Err(_) => continue,
+            };
+            if lines.iter().any(|l| l.address == first) {
+                return self.prepend_instructions(lines, p);
+            }
+        }
+        // Finally, the line table tells us where the instructions of the preceding source line
+        // start.
+        match self
+            .instructions
+            .first()
+            .and_then(|l| l.src_position.clone())
+        {
+            Some(SrcPosition { ref file, line }) if line.raw_value() > 1 => {
+                let mode = if self.show_opcodes {
+                    DisassembleMode::MixedSourceAndDisassemblyWithRawOpcodes
+                } else {
+                    DisassembleMode::MixedSourceAndDisassembly
+                };
+                let lines = p
+                    .gdb
+                    .mi
+                    .execute(MiCommand::data_disassemble_file(
+                        file,
+                        line.raw_value() - 1,
+                        None,
+                        mode,
+                    ))
+                    .map_err(GDBResponseError::Execution)
+                    .and_then(|result| Self::get_instructions(&result.results));
+                match lines {
+                    Ok(lines) => self.prepend_instructions(lines, p),
+                    Err(_) => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    // The debug location of the (probably misaligned) instruction right before the given address
+    // tells us where the function that it belongs to starts.
+    fn function_start_before(address: Address, p: ::UpdateParameters) -> Option<Address> {
+        let previous = address.0.checked_sub(1).map(Address)?;
+        let lines =
+            disassemble_address(previous, address, DisassembleMode::DisassemblyOnly, p).ok()?;
+        let location = AssemblyDebugLocation::try_from_value(lines.first()?)?;
+        previous.0.checked_sub(location.offset).map(Address)
+    }
+
+    fn prepend_instructions(&mut self, lines: Vec<AssemblyLine>, p: ::UpdateParameters) -> bool {
+        let first = if let Some(line) = self.instructions.first() {
+            line.address
+        } else {
+            return false;
+        };
+        let mut instructions = lines
+            .into_iter()
+            .filter(|l| l.address < first)
+            .collect::<Vec<_>>();
+        if instructions.is_empty() {
+            return false;
+        }
+        instructions.extend(self.instructions.drain(..));
+        self.instructions = instructions;
+        self.show_extended_instructions(p);
+        true
+    }
+
+    // Load the instructions of the extended range that lie outside of the loaded content again.
+    // They are disassembled by address (like when they were loaded while scrolling).
+    fn restore_extended_range(
+        &mut self,
+        begin: Address,
+        end: Address,
+        p: ::UpdateParameters,
+    ) -> Result<(), DisassembleError> {
+        let (first, last) = match (self.instructions.first(), self.instructions.last()) {
+            (Some(first), Some(last)) => (first.address, last.address),
+            _ => return Ok(()),
+        };
+        let mut instructions = if begin < first {
+            self.disassemble_range(begin, first, p)?
+                .into_iter()
+                .filter(|l| l.address < first)
+                .collect()
+        } else {
+            Vec::new()
+        };
+        instructions.extend(self.instructions.drain(..));
+        if last + 1 < end {
+            instructions.extend(
+                self.disassemble_range(last, end, p)?
+                    .into_iter()
+                    .filter(|l| l.address > last),
+            );
+        }
+        self.instructions = instructions;
+        self.extended_range = Some((begin, end));
+        self.refresh_content(p);
+        Ok(())
+    }
+
+    // Disassemble the currently shown range again (e.g., after changing the disassembly settings).
+    fn reload(&mut self, p: ::UpdateParameters) -> Result<(), DisassembleError> {
+        let current_address = self.pager.current_line().map(|l| l.address);
+        let extended_range = self.extended_range;
+        match self.loaded_content.clone() {
+            Some(AsmContent::File(path, line)) => self.show_file(path, line, p)?,
+            Some(AsmContent::Addresses(begin, end)) => self.show_address(begin, end, p)?,
+            None => {}
+        }
+        if let Some((begin, end)) = extended_range {
+            self.restore_extended_range(begin, end, p)?;
+        }
+        if let Some(address) = current_address {
+            let _ = self.go_to_address(address);
         }
-        self.show_lines(lines, p);
         Ok(())
     }
 
+    fn follow_branch(&mut self, p: ::UpdateParameters) {
+        let (origin, target) = match self.pager.current_line() {
+            Some(&AssemblyLine {
+                address,
+                branch_target: Some(target),
+                ..
+            }) => (address, target),
+            _ => return,
+        };
+        let origin_content = self.loaded_content.clone();
+        if self.go_to_address(target).is_err() {
+            // The target lies outside of the current function, so we have to load its range.
+            let range = CodeWindow::find_function_range(target, p)
+                .or_else(|_| CodeWindow::find_valid_address_range(target, 128, p));
+            let loaded = match range {
+                Ok((begin, end)) => self.show_address(begin, end, p),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = loaded.and_then(|_| {
+                self.go_to_address(target)
+                    .map_err(|_| DisassembleError::Other("Target not disassembled".to_owned()))
+            }) {
+                p.message_sink
+                    .send(format!("Cannot follow branch to {}: {:?}", target, e));
+                return;
+            }
+        }
+        self.jump_history.push((origin_content, origin));
+    }
+
+    fn go_back(&mut self, p: ::UpdateParameters) {
+        if let Some((content, address)) = self.jump_history.pop() {
+            if content != self.loaded_content {
+                let reloaded = match content {
+                    Some(AsmContent::File(path, line)) => self.show_file(path, line, p),
+                    Some(AsmContent::Addresses(begin, end)) => self.show_address(begin, end, p),
+                    None => Ok(()),
+                };
+                if let Err(e) = reloaded {
+                    p.message_sink
+                        .send(format!("Failed to disassemble: {:?}", e));
+                    return;
+                }
+            }
+            let _ = self.go_to_address(address);
+        }
+    }
+
+    fn update_register_annotations(&mut self, p: ::UpdateParameters) {
+        self.register_annotations.clear();
+        let num_lines = match self.register_annotation_mode {
+            RegisterAnnotationMode::Off => 0,
+            RegisterAnnotationMode::StopPosition => 1,
+            RegisterAnnotationMode::StopPositionAndFollowing => {
+                1 + REGISTER_ANNOTATION_FOLLOWING_LINES
+            }
+        };
+        let stop_index = self.last_stop_position.and_then(|stop_position| {
+            self.instructions
+                .iter()
+                .position(|l| l.address == stop_position)
+        });
+        if let (Some(stop_index), true) = (stop_index, num_lines > 0) {
+            if let Some(registers) = RegisterNames::query(p) {
+                let lines = self
+                    .instructions
+                    .iter()
+                    .skip(stop_index)
+                    .take(num_lines)
+                    .collect::<Vec<_>>();
+                let annotations = annotate_instructions(
+                    &lines.iter().map(|l| l.content.as_str()).collect::<Vec<_>>(),
+                    &registers,
+                    p,
+                );
+                for (line, annotation) in lines.iter().zip(annotations) {
+                    if !annotation.is_empty() {
+                        self.register_annotations.insert(line.address, annotation);
+                    }
+                }
+            }
+        }
+        self.refresh_content(p);
+    }
+
+    fn toggle_register_annotations(&mut self, p: ::UpdateParameters) {
+        self.register_annotation_mode = match self.register_annotation_mode {
+            RegisterAnnotationMode::Off => RegisterAnnotationMode::StopPosition,
+            RegisterAnnotationMode::StopPosition => {
+                RegisterAnnotationMode::StopPositionAndFollowing
+            }
+            RegisterAnnotationMode::StopPositionAndFollowing => RegisterAnnotationMode::Off,
+        };
+        self.update_register_annotations(p);
+    }
+
+    fn toggle_opcodes(&mut self, p: ::UpdateParameters) {
+        self.show_opcodes = !self.show_opcodes;
+        if let Err(e) = self.reload(p) {
+            p.message_sink
+                .send(format!("Failed to disassemble: {:?}", e));
+        }
+    }
+
+    fn toggle_flavor(&mut self, p: ::UpdateParameters) {
+        let current_flavor = match p.gdb.mi.execute(MiCommand::gdb_show("disassembly-flavor")) {
+            Ok(ResultRecord {
+                class: ResultClass::Done,
+                results,
+                ..
+            }) => results["value"].as_str().unwrap_or("att").to_owned(),
+            Ok(_) | Err(_) => {
+                p.message_sink
+                    .send("Cannot change disassembly flavor: Gdb is busy.");
+                return;
+            }
+        };
+        let new_flavor = if current_flavor == "intel" {
+            "att"
+        } else {
+            "intel"
+        };
+        match p
+            .gdb
+            .mi
+            .execute(MiCommand::gdb_set("disassembly-flavor", new_flavor))
+        {
+            Ok(ResultRecord {
+                class: ResultClass::Done,
+                ..
+            }) => {
+                p.gdb.disassembly_cache.invalidate();
+            }
+            Ok(ResultRecord { results, .. }) => {
+                p.message_sink.send(format!(
+                    "Cannot change disassembly flavor: {}",
+                    results["msg"].as_str().unwrap_or("Unknown error")
+                ));
+                return;
+            }
+            Err(_) => {
+                p.message_sink
+                    .send("Cannot change disassembly flavor: Gdb is busy.");
+                return;
+            }
+        }
+        if let Err(e) = self.reload(p) {
+            p.message_sink
+                .send(format!("Failed to disassemble: {:?}", e));
+        }
+    }
+
     fn toggle_breakpoint(&self, p: ::UpdateParameters) {
         if let Some(line) = self.pager.current_line() {
             let active_bps: Vec<BreakPointNumber> = p
@@ -366,7 +1184,13 @@ impl<'a> AssemblyView<'a> {
                 .breakpoints
                 .values()
                 .filter_map(|bp| {
-                    if let Some(ref address) = bp.address {
+                    if line.source_header {
+                        if bp.src_pos == line.src_position {
+                            Some(bp.number)
+                        } else {
+                            None
+                        }
+                    } else if let Some(ref address) = bp.address {
                         if *address == line.address {
                             Some(bp.number)
                         } else {
@@ -378,10 +1202,13 @@ impl<'a> AssemblyView<'a> {
                 })
                 .collect();
             if active_bps.is_empty() {
-                match p
-                    .gdb
-                    .insert_breakpoint(BreakPointLocation::Address(line.address.0))
-                {
+                let location = match (line.source_header, &line.src_position) {
+                    (true, &Some(ref src_position)) => {
+                        BreakPointLocation::Line(&src_position.file, src_position.line.into())
+                    }
+                    _ => BreakPointLocation::Address(line.address.0),
+                };
+                match p.gdb.insert_breakpoint(location) {
                     Ok(()) => {}
                     Err(BreakpointOperationError::Busy) => {
                         p.message_sink
@@ -418,7 +1245,28 @@ impl<'a> AssemblyView<'a> {
                     .to_beginning_on(Key::Home)
                     .to_end_on(Key::End),
             )
+            .chain(|i: Input| {
+                // We are at the edge of the loaded instructions, so try to load more.
+                if (i.matches(Key::Down) || i.matches(Key::Char('j')))
+                    && self.load_following_instructions(p)
+                {
+                    let _ = self.pager.scroll_forwards();
+                    None
+                } else if (i.matches(Key::Up) || i.matches(Key::Char('k')))
+                    && self.load_preceding_instructions(p)
+                {
+                    let _ = self.pager.scroll_backwards();
+                    None
+                } else {
+                    Some(i)
+                }
+            })
             .chain((Key::Char(' '), || self.toggle_breakpoint(p)))
+            .chain((Key::Char('\n'), || self.follow_branch(p)))
+            .chain((Key::Backspace, || self.go_back(p)))
+            .chain((Key::Char('o'), || self.toggle_opcodes(p)))
+            .chain((Key::Char('r'), || self.toggle_register_annotations(p)))
+            .chain((Key::Char('x'), || self.toggle_flavor(p)))
             .finish()
     }
 }
@@ -432,17 +1280,40 @@ impl<'a> Widget for AssemblyView<'a> {
     }
 }
 
+fn severity_marker(severity: Severity) -> (char, StyleModifier) {
+    let color = match severity {
+        Severity::Info => Color::Cyan,
+        Severity::Warning => Color::Yellow,
+        Severity::Error => Color::Red,
+    };
+    ('■', StyleModifier::new().fg_color(color))
+}
+
+#[derive(Default)]
 struct SourceDecorator {
     stop_position: Option<LineNumber>,
+    live_position: Option<LineNumber>,
+    replaying: bool,
     breakpoint_lines: HashSet<LineNumber>,
+    // Most severe external annotation per line
+    annotation_lines: HashMap<LineNumber, Severity>,
 }
 
 impl SourceDecorator {
     fn new<'a, I: Iterator<Item = &'a BreakPoint>>(
         file: &Path,
         stop_position: Option<LineNumber>,
+        live_position: Option<&SrcPosition>,
         breakpoints: I,
+        annotations: &AnnotationSets,
     ) -> Self {
+        let mut annotation_lines = HashMap::new();
+        for annotation in annotations.for_file(file) {
+            let severity = annotation_lines
+                .entry(annotation.line)
+                .or_insert(annotation.severity);
+            *severity = ::std::cmp::max(*severity, annotation.severity);
+        }
         let addresses = breakpoints
             .filter_map(|bp| {
                 bp.src_pos.clone().and_then(|pos| {
@@ -456,22 +1327,37 @@ impl SourceDecorator {
             .collect();
         SourceDecorator {
             stop_position: stop_position,
+            live_position: live_position.and_then(|pos| {
+                if pos.file == file {
+                    Some(pos.line)
+                } else {
+                    None
+                }
+            }),
+            replaying: live_position.is_some(),
             breakpoint_lines: addresses,
+            annotation_lines: annotation_lines,
         }
     }
 }
 
+fn line_number_demand<'a, L: 'a>(
+    lines: impl DoubleEndedIterator<Item = (LineIndex, &'a L)>,
+) -> ColDemand {
+    let max_space = lines
+        .last()
+        .map(|(i, _)| text_width(format!(" {} ", i).as_str()))
+        .unwrap_or(Width::new(0).unwrap());
+    Demand::from_to(0, max_space.into())
+}
+
 impl LineDecorator for SourceDecorator {
     type Line = String;
     fn horizontal_space_demand<'a, 'b: 'a>(
         &'a self,
         lines: impl DoubleEndedIterator<Item = (LineIndex, &'b Self::Line)> + 'b,
     ) -> ColDemand {
-        let max_space = lines
-            .last()
-            .map(|(i, _)| text_width(format!(" {} ", i).as_str()))
-            .unwrap_or(Width::new(0).unwrap());
-        Demand::from_to(0, max_space.into())
+        line_number_demand(lines)
     }
     fn decorate(
         &self,
@@ -488,24 +1374,29 @@ impl LineDecorator for SourceDecorator {
             .stop_position
             .map(|p| p == current_index.into())
             .unwrap_or(false);
+        let at_live_position = self.live_position == Some(current_index.into());
         let at_breakpoint_position = self.breakpoint_lines.contains(&current_index.into());
 
-        let (right_border, style_modifier) = match (at_stop_position, at_breakpoint_position) {
-            (true, true) => ('▶', StyleModifier::new().fg_color(Color::Red).bold(true)),
-            (true, false) => (
-                '▶',
-                StyleModifier::new().fg_color(Color::Green).bold(true),
-            ),
-            (false, true) => ('●', StyleModifier::new().fg_color(Color::Red)),
-            (false, false) => (' ', StyleModifier::new()),
-        };
-
-        cursor.set_style_modifier(style_modifier);
+        let (right_border, style_modifier) = position_marker(
+            at_stop_position,
+            at_live_position,
+            at_breakpoint_position,
+            self.replaying,
+        );
 
         use std::fmt::Write;
+        if let Some(&severity) = self.annotation_lines.get(&line_number) {
+            let (marker, marker_style) = severity_marker(severity);
+            cursor.set_style_modifier(marker_style);
+            write!(cursor, "{}", marker).unwrap();
+        } else {
+            write!(cursor, " ").unwrap();
+        }
+
+        cursor.set_style_modifier(style_modifier);
         write!(
             cursor,
-            " {:width$}{}",
+            "{:width$}{}",
             line_number,
             right_border,
             width = width.into()
@@ -514,9 +1405,220 @@ impl LineDecorator for SourceDecorator {
     }
 }
 
+// Number of lines before the stop position (in addition to the stop line itself) for which the
+// values of referenced variables are displayed.
+const INLINE_VALUE_CONTEXT_LINES: usize = 5;
+const INLINE_VALUE_MAX_WIDTH: usize = 20;
+const INLINE_ANNOTATION_MAX_WIDTH: usize = 80;
+
+// Reuses the (expensive to compute) highlighting of the original lines and displays the
+// annotations that have been appended to some lines in a dimmed style as well as the column
+// cursor.
+struct AnnotatedHighlighter<'a> {
+    base: &'a HighlightInfo,
+    annotation_starts: &'a [Option<usize>],
+    cursor: Option<(LineIndex, Range<usize>)>,
+}
+
+// Apply the given style on top of all style changes in the given range of the line.
+fn mark_range(
+    changes: &[(usize, StyleModifier)],
+    range: &Range<usize>,
+    mark_style: StyleModifier,
+) -> Vec<(usize, StyleModifier)> {
+    let style_at = |pos: usize| {
+        changes
+            .iter()
+            .take_while(|&&(change_pos, _)| change_pos <= pos)
+            .last()
+            .map(|&(_, style)| style)
+            .unwrap_or(StyleModifier::new())
+    };
+    let mut marked = changes
+        .iter()
+        .filter(|&&(pos, _)| pos < range.start)
+        .cloned()
+        .collect::<Vec<_>>();
+    marked.push((range.start, mark_style.on_top_of(style_at(range.start))));
+    marked.extend(
+        changes
+            .iter()
+            .filter(|&&(pos, _)| range.start < pos && pos < range.end)
+            .map(|&(pos, style)| (pos, mark_style.on_top_of(style))),
+    );
+    marked.push((range.end, style_at(range.end)));
+    marked.extend(changes.iter().filter(|&&(pos, _)| pos > range.end).cloned());
+    marked
+}
+
+impl<'a> Highlighter for AnnotatedHighlighter<'a> {
+    fn highlight<'b, L: Iterator<Item = &'b dyn PagerLine>>(&self, _lines: L) -> HighlightInfo {
+        let mut info = HighlightInfo::none();
+        info.default_style = self.base.default_style();
+        info.style_changes = self
+            .annotation_starts
+            .iter()
+            .enumerate()
+            .map(|(i, annotation_start)| {
+                let mut changes = self.base.get_info_for_line(LineIndex::new(i)).clone();
+                if let &Some(start) = annotation_start {
+                    changes.push((
+                        start,
+                        StyleModifier::new()
+                            .fg_color(Color::LightBlack)
+                            .italic(true),
+                    ));
+                }
+                match self.cursor {
+                    Some((line, ref range)) if line == LineIndex::new(i) => mark_range(
+                        &changes,
+                        range,
+                        StyleModifier::new()
+                            .invert(BoolModifyMode::Toggle)
+                            .underline(true),
+                    ),
+                    _ => changes,
+                }
+            })
+            .collect();
+        info
+    }
+}
+
+fn truncate_with_ellipsis(s: &str, max_chars: usize) -> String {
+    if s.chars().count() > max_chars {
+        let mut truncated = s
+            .chars()
+            .take(max_chars.saturating_sub(1))
+            .collect::<String>();
+        truncated.push('…');
+        truncated
+    } else {
+        s.to_owned()
+    }
+}
+
+// Find all identifiers in the line that may refer to variables, i.e., skip member accesses.
+fn referenced_identifiers(line: &str) -> Vec<&str> {
+    let mut identifiers = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    let mut prev_non_ident = (' ', ' ');
+    while let Some((begin, c)) = chars.next() {
+        if c.is_alphabetic() || c == '_' {
+            let mut end = begin + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let is_member_access = prev_non_ident.1 == '.' || prev_non_ident == ('-', '>');
+            let identifier = &line[begin..end];
+            if !is_member_access && !identifiers.contains(&identifier) {
+                identifiers.push(identifier);
+            }
+            prev_non_ident = (' ', ' ');
+        } else if c.is_numeric() {
+            // Skip numeric literals (including suffixes like 0x10ull)
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            prev_non_ident = (' ', ' ');
+        } else if !c.is_whitespace() {
+            prev_non_ident = (prev_non_ident.1, c);
+        }
+    }
+    identifiers
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+// Clamp the (desired) column to the given line.
+fn column_in(line: &str, column: usize) -> usize {
+    let mut column = column.min(line.len().saturating_sub(1));
+    while !line.is_char_boundary(column) {
+        column -= 1;
+    }
+    column
+}
+
+// Find the range of the member access chain (e.g., "a.b->c" or "arr[i].x") that ends with the
+// identifier at the given column.
+fn expression_range_at(line: &str, column: usize) -> Option<Range<usize>> {
+    let bytes = line.as_bytes();
+    if column >= bytes.len() || !is_identifier_byte(bytes[column]) {
+        return None;
+    }
+    let mut end = column;
+    while end < bytes.len() && is_identifier_byte(bytes[end]) {
+        end += 1;
+    }
+    let mut begin = column;
+    while begin > 0 && is_identifier_byte(bytes[begin - 1]) {
+        begin -= 1;
+    }
+    if bytes[begin].is_ascii_digit() {
+        return None;
+    }
+    loop {
+        let separator_len = {
+            let before = &line[..begin];
+            if before.ends_with("->") || before.ends_with("::") {
+                2
+            } else if before.ends_with('.') {
+                1
+            } else {
+                break;
+            }
+        };
+        let mut new_begin = begin - separator_len;
+        // Skip (possibly nested) subscripts
+        while new_begin > 0 && bytes[new_begin - 1] == b']' {
+            let mut depth = 0;
+            let mut pos = new_begin;
+            loop {
+                if pos == 0 {
+                    return Some(begin..end);
+                }
+                pos -= 1;
+                match bytes[pos] {
+                    b']' => depth += 1,
+                    b'[' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            new_begin = pos;
+        }
+        let identifier_end = new_begin;
+        while new_begin > 0 && is_identifier_byte(bytes[new_begin - 1]) {
+            new_begin -= 1;
+        }
+        if new_begin == identifier_end || bytes[new_begin].is_ascii_digit() {
+            break;
+        }
+        begin = new_begin;
+    }
+    Some(begin..end)
+}
+
 struct FileInfo {
     path: PathBuf,
     modified: ::std::time::SystemTime,
+    lines: Vec<String>,
+    highlight_info: HighlightInfo,
 }
 
 pub struct SourceView<'a> {
@@ -525,6 +1627,15 @@ pub struct SourceView<'a> {
     pager: Pager<String, SourceDecorator>,
     file_info: Option<FileInfo>,
     last_stop_position: Option<SrcPosition>,
+    // Only present while replaying recorded execution
+    live_position: Option<SrcPosition>,
+    show_inline_values: bool,
+    variable_values: HashMap<String, String>,
+    cursor_column: usize,
+    selection_anchor: Option<(LineIndex, usize)>,
+    // Start of the annotation appended to each line of the pager content (if any)
+    annotation_starts: Vec<Option<usize>>,
+    displayed_cursor: Option<(LineIndex, Range<usize>)>,
 }
 
 macro_rules! current_file_and_content_mut {
@@ -546,12 +1657,23 @@ impl<'a> SourceView<'a> {
             pager: Pager::new(),
             file_info: None,
             last_stop_position: None,
+            live_position: None,
+            show_inline_values: true,
+            variable_values: HashMap::new(),
+            cursor_column: 0,
+            selection_anchor: None,
+            annotation_starts: Vec::new(),
+            displayed_cursor: None,
         }
     }
     fn set_last_stop_position<P: AsRef<Path>>(&mut self, file: P, pos: LineNumber) {
         self.last_stop_position = Some(SrcPosition::new(file.as_ref().to_path_buf(), pos));
     }
 
+    fn set_live_position(&mut self, pos: Option<SrcPosition>) {
+        self.live_position = pos;
+    }
+
     fn go_to_line<L: Into<LineNumber>>(&mut self, line: L) -> Result<(), GotoError> {
         Ok(self.pager.go_to_line(line.into())?)
     }
@@ -598,7 +1720,9 @@ impl<'a> SourceView<'a> {
             content.set_decorator(SourceDecorator::new(
                 file_path,
                 last_line_number,
+                self.live_position.as_ref(),
                 p.gdb.breakpoints.values(),
+                &p.annotations,
             ));
         }
     }
@@ -625,7 +1749,7 @@ impl<'a> SourceView<'a> {
     ) -> Result<(), PagerShowError> {
         if self.need_to_load_file(path.as_ref()) {
             let path_ref = path.as_ref();
-            self.load(path_ref, p.gdb.breakpoints.values())
+            self.load(path_ref, p)
                 .map_err(|e| PagerShowError::CouldNotOpenFile(path_ref.to_path_buf(), e))?;
         } else {
             let last_line_number = self.get_last_line_number_for(path.as_ref());
@@ -633,41 +1757,357 @@ impl<'a> SourceView<'a> {
                 content.set_decorator(SourceDecorator::new(
                     path.as_ref(),
                     last_line_number,
+                    self.live_position.as_ref(),
                     p.gdb.breakpoints.values(),
+                    &p.annotations,
                 ));
             }
         }
         Ok(())
     }
 
-    fn load<'b, P: AsRef<Path>, I: Iterator<Item = &'b BreakPoint>>(
-        &mut self,
-        path: P,
-        breakpoints: I,
-    ) -> io::Result<()> {
-        let pager_content = PagerContent::from_file(path.as_ref())?;
-        let syntax = self
-            .syntax_set
-            .find_syntax_for_file(path.as_ref())
-            .expect("file IS openable, see pager content")
-            .unwrap_or(self.syntax_set.find_syntax_plain_text());
-        let last_line_number = self.get_last_line_number_for(path.as_ref());
-        self.pager.load(
-            pager_content
-                .with_highlighter(&SyntectHighlighter::new(syntax, self.highlighting_theme))
-                .with_decorator(SourceDecorator::new(
-                    path.as_ref(),
-                    last_line_number,
-                    breakpoints,
-                )),
-        );
+    fn load<P: AsRef<Path>>(&mut self, path: P, p: ::UpdateParameters) -> io::Result<()> {
+        let lines = fs::read_to_string(path.as_ref())?
+            .lines()
+            .map(|s| s.to_owned())
+            .collect::<Vec<_>>();
+        let highlight_info = {
+            let syntax = self
+                .syntax_set
+                .find_syntax_for_file(path.as_ref())
+                .expect("file IS openable, see above")
+                .unwrap_or(self.syntax_set.find_syntax_plain_text());
+            SyntectHighlighter::new(syntax, self.highlighting_theme)
+                .highlight(lines.iter().map(|l| l as &dyn PagerLine))
+        };
         self.file_info = Some(FileInfo {
             path: path.as_ref().to_owned(),
             modified: fs::metadata(path)?.modified()?,
+            lines: lines,
+            highlight_info: highlight_info,
         });
+        self.refresh_content(p);
         Ok(())
     }
 
+    fn inline_value_annotations(&self, file_info: &FileInfo) -> HashMap<LineNumber, String> {
+        let mut annotations = HashMap::new();
+        let stop_line = match self.last_stop_position {
+            Some(ref pos) if self.show_inline_values && pos.file == file_info.path => pos.line,
+            _ => return annotations,
+        };
+        let stop_index: LineIndex = stop_line.into();
+        let first_index = stop_index
+            .raw_value()
+            .saturating_sub(INLINE_VALUE_CONTEXT_LINES);
+        for index in first_index..=stop_index.raw_value() {
+            if let Some(line) = file_info.lines.get(index) {
+                let values = referenced_identifiers(line)
+                    .into_iter()
+                    .filter_map(|identifier| {
+                        self.variable_values.get(identifier).map(|value| {
+                            format!(
+                                "{} = {}",
+                                identifier,
+                                truncate_with_ellipsis(value, INLINE_VALUE_MAX_WIDTH)
+                            )
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                if !values.is_empty() {
+                    annotations.insert(
+                        LineIndex::new(index).into(),
+                        truncate_with_ellipsis(&values.join(", "), INLINE_ANNOTATION_MAX_WIDTH),
+                    );
+                }
+            }
+        }
+        annotations
+    }
+
+    // (Re)build the pager content from the loaded file and its annotations.
+    fn refresh_content(&mut self, p: ::UpdateParameters) {
+        let file_info = if let Some(ref file_info) = self.file_info {
+            file_info
+        } else {
+            return;
+        };
+        let mut annotations = self.inline_value_annotations(file_info);
+        for annotation in p.annotations.for_file(&file_info.path) {
+            if annotation.text.is_empty() {
+                continue;
+            }
+            let text = truncate_with_ellipsis(&annotation.text, INLINE_ANNOTATION_MAX_WIDTH);
+            let line_annotation = annotations.entry(annotation.line).or_insert(String::new());
+            if !line_annotation.is_empty() {
+                line_annotation.push_str("  ");
+            }
+            line_annotation.push_str(&text);
+        }
+        let mut annotation_starts = Vec::with_capacity(file_info.lines.len());
+        let lines = file_info
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                if let Some(annotation) = annotations.get(&LineIndex::new(i).into()) {
+                    annotation_starts.push(Some(line.len()));
+                    format!("{}  {}", line, annotation)
+                } else {
+                    annotation_starts.push(None);
+                    line.clone()
+                }
+            })
+            .collect::<Vec<_>>();
+        let last_line_number = self.get_last_line_number_for(&file_info.path);
+        let cursor = self
+            .cursor_range()
+            .map(|range| (self.pager.current_line_index(), range));
+        let content = PagerContent::from_lines(lines)
+            .with_highlighter(&AnnotatedHighlighter {
+                base: &file_info.highlight_info,
+                annotation_starts: &annotation_starts,
+                cursor: cursor.clone(),
+            })
+            .with_decorator(SourceDecorator::new(
+                &file_info.path,
+                last_line_number,
+                self.live_position.as_ref(),
+                p.gdb.breakpoints.values(),
+                &p.annotations,
+            ));
+        self.pager.load(content);
+        self.annotation_starts = annotation_starts;
+        self.displayed_cursor = cursor;
+    }
+
+    fn current_line_content(&self) -> Option<&str> {
+        self.file_info.as_ref().and_then(|file_info| {
+            file_info
+                .lines
+                .get(self.pager.current_line_index().raw_value())
+                .map(|l| l.as_str())
+        })
+    }
+
+    // Byte range of the character under the cursor or the current selection in the current line.
+    fn cursor_range(&self) -> Option<Range<usize>> {
+        let line = self.current_line_content()?;
+        let column = column_in(line, self.cursor_column);
+        let (begin, end) = match self.selection_anchor {
+            Some((index, anchor)) if index == self.pager.current_line_index() => {
+                (anchor.min(column), anchor.max(column))
+            }
+            _ => (column, column),
+        };
+        let end = end
+            + line[end..]
+                .chars()
+                .next()
+                .map(|c| c.len_utf8())
+                .unwrap_or(0);
+        if begin < end {
+            Some(begin..end)
+        } else {
+            None
+        }
+    }
+
+    // Only the highlighting of the pager content has to be recomputed if the cursor moved, so
+    // neither the lines nor the syntax highlighting of the file are rebuilt.
+    fn update_cursor(&mut self) {
+        if let Some((index, _)) = self.selection_anchor {
+            if index != self.pager.current_line_index() {
+                self.selection_anchor = None;
+            }
+        }
+        let cursor = self
+            .cursor_range()
+            .map(|range| (self.pager.current_line_index(), range));
+        if cursor == self.displayed_cursor {
+            return;
+        }
+        let file_info = if let Some(ref file_info) = self.file_info {
+            file_info
+        } else {
+            return;
+        };
+        if let Some(content) = self.pager.content_mut() {
+            let placeholder =
+                PagerContent::from_lines(Vec::new()).with_decorator(SourceDecorator::default());
+            let highlighted =
+                ::std::mem::replace(content, placeholder).with_highlighter(&AnnotatedHighlighter {
+                    base: &file_info.highlight_info,
+                    annotation_starts: &self.annotation_starts,
+                    cursor: cursor.clone(),
+                });
+            *content = highlighted;
+        }
+        self.displayed_cursor = cursor;
+    }
+
+    fn move_cursor_left(&mut self) {
+        if let Some(line) = self.current_line_content() {
+            let column = column_in(line, self.cursor_column);
+            self.cursor_column = line[..column]
+                .chars()
+                .next_back()
+                .map(|c| column - c.len_utf8())
+                .unwrap_or(column);
+        }
+    }
+
+    fn move_cursor_right(&mut self) {
+        if let Some(line) = self.current_line_content() {
+            let column = column_in(line, self.cursor_column);
+            self.cursor_column = column_in(
+                line,
+                column
+                    + line[column..]
+                        .chars()
+                        .next()
+                        .map(|c| c.len_utf8())
+                        .unwrap_or(0),
+            );
+        }
+    }
+
+    fn move_cursor_to_next_word(&mut self) {
+        if let Some(line) = self.current_line_content() {
+            let bytes = line.as_bytes();
+            let mut column = column_in(line, self.cursor_column);
+            while column < bytes.len() && is_identifier_byte(bytes[column]) {
+                column += 1;
+            }
+            while column < bytes.len() && !is_identifier_byte(bytes[column]) {
+                column += 1;
+            }
+            if column < bytes.len() {
+                self.cursor_column = column;
+            }
+        }
+    }
+
+    fn move_cursor_to_previous_word(&mut self) {
+        if let Some(line) = self.current_line_content() {
+            let bytes = line.as_bytes();
+            let mut column = column_in(line, self.cursor_column);
+            while column > 0 && !is_identifier_byte(bytes[column - 1]) {
+                column -= 1;
+            }
+            while column > 0 && is_identifier_byte(bytes[column - 1]) {
+                column -= 1;
+            }
+            if line.is_char_boundary(column) {
+                self.cursor_column = column;
+            }
+        }
+    }
+
+    fn toggle_selection(&mut self) {
+        self.selection_anchor = if self.selection_anchor.is_some() {
+            None
+        } else {
+            self.current_line_content().map(|line| {
+                (
+                    self.pager.current_line_index(),
+                    column_in(line, self.cursor_column),
+                )
+            })
+        };
+    }
+
+    // Use syntax information to avoid evaluating comments, strings, keywords or similar.
+    fn is_code_at(&self, line: &str, column: usize) -> bool {
+        let syntax = match self
+            .file_info
+            .as_ref()
+            .and_then(|f| self.syntax_set.find_syntax_for_file(&f.path).ok())
+        {
+            Some(Some(syntax)) => syntax,
+            _ => return true,
+        };
+        let mut scopes = ScopeStack::new();
+        for (pos, op) in ParseState::new(syntax).parse_line(line) {
+            if pos > column {
+                break;
+            }
+            scopes.apply(&op);
+        }
+        let non_code_scopes = ["comment", "string", "keyword", "storage"]
+            .iter()
+            .map(|s| Scope::new(s).expect("valid scope"))
+            .collect::<Vec<_>>();
+        !scopes.as_slice().iter().any(|&scope| {
+            non_code_scopes
+                .iter()
+                .any(|non_code| non_code.is_prefix_of(scope))
+        })
+    }
+
+    fn expression_under_cursor(&self) -> Option<String> {
+        let line = self.current_line_content()?;
+        if let Some((index, _)) = self.selection_anchor {
+            if index == self.pager.current_line_index() {
+                let selection = line[self.cursor_range()?].trim();
+                return if selection.is_empty() {
+                    None
+                } else {
+                    Some(selection.to_owned())
+                };
+            }
+        }
+        let column = column_in(line, self.cursor_column);
+        if !self.is_code_at(line, column) {
+            return None;
+        }
+        expression_range_at(line, column).map(|range| line[range].to_owned())
+    }
+
+    fn update_variable_values(&mut self, p: ::UpdateParameters) {
+        self.variable_values.clear();
+        if self.show_inline_values {
+            match p.gdb.mi.execute(MiCommand::stack_list_variables(
+                ValuePrintMode::SimpleValues,
+            )) {
+                Ok(ResultRecord {
+                    class: ResultClass::Done,
+                    results,
+                    ..
+                }) => {
+                    // Shadowed variables are listed under the same name, but we cannot tell
+                    // which declaration an occurrence in the source refers to. Rather than
+                    // showing a wrong value, we do not show any for these names.
+                    let mut occurrences = HashMap::new();
+                    for variable in results["variables"].members() {
+                        if let Some(name) = variable["name"].as_str() {
+                            *occurrences.entry(name).or_insert(0) += 1;
+                        }
+                    }
+                    for variable in results["variables"].members() {
+                        // Values of complex types are not listed with SimpleValues.
+                        if let (Some(name), Some(value)) =
+                            (variable["name"].as_str(), variable["value"].as_str())
+                        {
+                            if occurrences[name] == 1 {
+                                self.variable_values
+                                    .insert(name.to_owned(), value.replace('\n', " "));
+                            }
+                        }
+                    }
+                }
+                Ok(o) => warn!("Failed to list variables: {:?}", o),
+                Err(e) => warn!("Failed to list variables: {:?}", e),
+            }
+        }
+        self.refresh_content(p);
+    }
+
+    fn toggle_inline_values(&mut self, p: ::UpdateParameters) {
+        self.show_inline_values = !self.show_inline_values;
+        self.update_variable_values(p);
+    }
+
     fn current_line_number(&self) -> LineNumber {
         self.pager.current_line_index().into()
     }
@@ -716,8 +2156,51 @@ impl<'a> SourceView<'a> {
         }
     }
 
+    // Jumps to the next (or previous) line with external annotations, wrapping around at the end
+    // (or beginning) of the file.
+    fn go_to_annotation(&mut self, forward: bool, p: ::UpdateParameters) {
+        let lines = if let Some(path) = self.current_file() {
+            p.annotations
+                .for_file(path)
+                .into_iter()
+                .map(|annotation| annotation.line.raw_value())
+                .collect::<Vec<_>>()
+        } else {
+            return;
+        };
+        let current = self.current_line_number().raw_value();
+        let target = if forward {
+            lines.iter().find(|&&line| line > current).or(lines.first())
+        } else {
+            lines
+                .iter()
+                .rev()
+                .find(|&&line| line < current)
+                .or(lines.last())
+        };
+        if let Some(&line) = target {
+            let _ = self.go_to_line(LineNumber::new(line));
+        } else {
+            p.message_sink.send("No annotations in this file.");
+        }
+    }
+
+    fn open_in_editor(&self, p: ::UpdateParameters) {
+        if let Some(path) = self.current_file() {
+            p.editor.open(path, self.current_line_number());
+        }
+    }
+
+    fn toggle_editor_follow(&self, p: ::UpdateParameters) {
+        match p.editor.toggle_follow() {
+            Ok(true) => p.message_sink.send("Editor follow mode enabled."),
+            Ok(false) => p.message_sink.send("Editor follow mode disabled."),
+            Err(e) => p.message_sink.send(format!("{}", e)),
+        }
+    }
+
     fn event(&mut self, event: Input, p: ::UpdateParameters) -> Option<Input> {
-        event
+        let ret = event
             .chain(
                 ScrollBehavior::new(&mut self.pager)
                     .forwards_on(Key::Down)
@@ -728,7 +2211,21 @@ impl<'a> SourceView<'a> {
                     .to_end_on(Key::End),
             )
             .chain((Key::Char(' '), || self.toggle_breakpoint(p)))
-            .finish()
+            .chain((Key::Char('v'), || self.toggle_inline_values(p)))
+            .chain((Key::Left, || self.move_cursor_left()))
+            .chain((Key::Char('h'), || self.move_cursor_left()))
+            .chain((Key::Right, || self.move_cursor_right()))
+            .chain((Key::Char('l'), || self.move_cursor_right()))
+            .chain((Key::Char('w'), || self.move_cursor_to_next_word()))
+            .chain((Key::Char('b'), || self.move_cursor_to_previous_word()))
+            .chain((Key::Char('V'), || self.toggle_selection()))
+            .chain((Key::Char('a'), || self.go_to_annotation(true, p)))
+            .chain((Key::Char('A'), || self.go_to_annotation(false, p)))
+            .chain((Key::Char('e'), || self.open_in_editor(p)))
+            .chain((Key::Char('E'), || self.toggle_editor_follow(p)))
+            .finish();
+        self.update_cursor();
+        ret
     }
 }
 
@@ -746,6 +2243,7 @@ enum DisplayMode {
     Source,
     Assembly,
     SideBySide,
+    Interleaved,
     Message(String),
 }
 #[derive(Clone, PartialEq)]
@@ -769,6 +2267,10 @@ struct StackInfo {
     stack_depth: Option<u64>,
     file_path: Option<PathBuf>,
     function: Option<String>,
+    record_state: Option<RecordState>,
+    record_method: Option<String>,
+    replay_event: Option<u64>,
+    replay_tick: Option<u64>,
 }
 
 impl Widget for StackInfo {
@@ -797,6 +2299,26 @@ impl Widget for StackInfo {
         use std::fmt::Write;
         let width = window.get_width();
         let mut cursor = Cursor::new(&mut window).style_modifier(StyleModifier::new().bold(true));
+        let record_indicator = match self.record_state {
+            Some(RecordState::Recording) => Some(("REC", Color::Red)),
+            Some(RecordState::Replaying) => Some(("REPLAY", Color::Yellow)),
+            _ => None,
+        };
+        if let Some((label, color)) = record_indicator {
+            let mut cursor = cursor.save().style_modifier();
+            cursor.set_style_modifier(StyleModifier::new().fg_color(color).bold(true));
+            let _ = write!(cursor, "{}", label);
+            if let Some(ref method) = self.record_method {
+                let _ = write!(cursor, "({})", method);
+            }
+            if let Some(event) = self.replay_event {
+                let _ = write!(cursor, " event {}", event);
+            }
+            if let Some(tick) = self.replay_tick {
+                let _ = write!(cursor, " tick {}", tick);
+            }
+            let _ = write!(cursor, " ");
+        }
         let _ = write!(cursor, "[");
         if let Some(l) = self.stack_level {
             let _ = write!(cursor, "{}", l);
@@ -857,12 +2379,20 @@ impl From<ExecuteError> for DisassembleError {
 fn disassemble_address(
     address_start: Address,
     address_end: Address,
+    mode: DisassembleMode,
     p: ::UpdateParameters,
 ) -> Result<Vec<JsonValue>, DisassembleError> {
+    if let Some(lines) = p
+        .gdb
+        .disassembly_cache
+        .block(address_start, address_end, mode)
+    {
+        return Ok(lines.clone());
+    }
     let mut disass_results = match p.gdb.mi.execute(MiCommand::data_disassemble_address(
         address_start.0,
         address_end.0,
-        DisassembleMode::DisassemblyOnly,
+        mode,
     )) {
         Ok(o) => {
             if o.class == ResultClass::Error {
@@ -887,7 +2417,11 @@ fn disassemble_address(
         //I'm not sure if GDB does this already, but we better not rely on it...
         line_objs.sort_by_key(|(a, _)| *a);
 
-        Ok(line_objs.into_iter().map(|(_, o)| o).collect::<Vec<_>>())
+        let lines = line_objs.into_iter().map(|(_, o)| o).collect::<Vec<_>>();
+        p.gdb
+            .disassembly_cache
+            .insert_block(address_start, address_end, mode, lines.clone());
+        Ok(lines)
     } else {
         Err(GDBResponseError::MissingField(
             "asm_insns",
@@ -896,6 +2430,111 @@ fn disassemble_address(
     }
 }
 
+#[derive(Clone, Copy)]
+enum ExecutionCommand {
+    Next,
+    Step,
+    Continue,
+    Finish,
+}
+
+#[derive(Clone, PartialEq)]
+enum JumpTarget {
+    Line(PathBuf, LineNumber),
+    Address(Address),
+}
+
+impl JumpTarget {
+    fn location<'b>(&'b self) -> BreakPointLocation<'b> {
+        match self {
+            &JumpTarget::Line(ref path, line) => BreakPointLocation::Line(path, line.into()),
+            &JumpTarget::Address(address) => BreakPointLocation::Address(address.0),
+        }
+    }
+
+    fn function_name(&self, p: ::UpdateParameters) -> Result<Option<String>, DisassembleError> {
+        let line_objs = match self {
+            &JumpTarget::Address(address) => {
+                disassemble_address(address, address + 1, DisassembleMode::DisassemblyOnly, p)?
+            }
+            &JumpTarget::Line(ref path, line) => {
+                let mut disass_results = p
+                    .gdb
+                    .mi
+                    .execute(MiCommand::data_disassemble_file(
+                        path,
+                        line.into(),
+                        Some(1),
+                        DisassembleMode::DisassemblyOnly,
+                    ))?
+                    .results;
+                if let JsonValue::Array(line_objs) = disass_results["asm_insns"].take() {
+                    line_objs
+                } else {
+                    return Err(GDBResponseError::MissingField(
+                        "asm_insns",
+                        JsonValue::Object(disass_results),
+                    )
+                    .into());
+                }
+            }
+        };
+        Ok(line_objs
+            .first()
+            .and_then(|l| l["func-name"].as_str())
+            .map(|s| s.to_owned()))
+    }
+}
+
+// Displays the result of the evaluation of an expression from the source view.
+struct EvaluationPopup {
+    expression: String,
+    viewer: JsonViewer,
+}
+
+impl EvaluationPopup {
+    fn new(expression: String, value: &JsonValue) -> Self {
+        EvaluationPopup {
+            expression: expression,
+            viewer: JsonViewer::new(value),
+        }
+    }
+
+    fn height_in(&self, available: Height) -> i32 {
+        let content_height = self.viewer.space_demand().height.min.raw_value();
+        (content_height + 1).min(available.raw_value() / 2)
+    }
+}
+
+impl Widget for EvaluationPopup {
+    fn space_demand(&self) -> Demand2D {
+        self.viewer.space_demand().add_vertical(Demand2D {
+            width: Demand::at_least(1),
+            height: Demand::exact(1),
+        })
+    }
+    fn draw(&self, window: Window, hints: RenderingHints) {
+        use std::fmt::Write;
+        match window.split(RowIndex::new(1)) {
+            Ok((mut title, content)) => {
+                {
+                    let mut cursor = Cursor::new(&mut title).style_modifier(
+                        StyleModifier::new()
+                            .invert(BoolModifyMode::Toggle)
+                            .bold(true),
+                    );
+                    let _ = write!(cursor, "{}", self.expression);
+                    cursor.set_style_modifier(StyleModifier::new().invert(BoolModifyMode::Toggle));
+                    let _ = write!(cursor, "  (a: add to expression table, q: close)");
+                    cursor.fill_and_wrap_line();
+                }
+                self.viewer.draw(content, hints);
+            }
+            Err(window) => self.viewer.draw(window, hints),
+        }
+    }
+}
+
 pub struct CodeWindow<'a> {
     src_view: SourceView<'a>,
     asm_view: AssemblyView<'a>,
@@ -904,7 +2543,10 @@ pub struct CodeWindow<'a> {
     src_state: SrcContentState,
     asm_state: AsmContentState,
     last_bp_update: ::std::time::Instant,
+    last_annotation_update: ::std::time::Instant,
     stack_info: StackInfo,
+    pending_jump: Option<JumpTarget>,
+    evaluation_popup: Option<EvaluationPopup>,
 }
 
 impl<'a> CodeWindow<'a> {
@@ -919,7 +2561,10 @@ impl<'a> CodeWindow<'a> {
             src_state: SrcContentState::Unavailable,
             asm_state: AsmContentState::Unavailable,
             last_bp_update: ::std::time::Instant::now(),
+            last_annotation_update: ::std::time::Instant::now(),
             stack_info: Default::default(),
+            pending_jump: None,
+            evaluation_popup: None,
         }
     }
 
@@ -935,6 +2580,8 @@ impl<'a> CodeWindow<'a> {
             }
             (DisplayMode::SideBySide, SrcContentState::Available, _) => DisplayMode::Source,
             (DisplayMode::SideBySide, _, AsmContentState::Available) => DisplayMode::Assembly,
+            (DisplayMode::Interleaved, _, AsmContentState::Available) => DisplayMode::Interleaved,
+            (DisplayMode::Interleaved, SrcContentState::Available, _) => DisplayMode::Source,
             (_, _, _) => DisplayMode::Message("Neither source nor assembly available!".to_owned()),
         }
     }
@@ -988,7 +2635,7 @@ impl<'a> CodeWindow<'a> {
                     warn!("Failed to load assembly: {:?}", e);
                 }
             }
-            DisplayMode::Assembly => {
+            DisplayMode::Assembly | DisplayMode::Interleaved => {
                 if let Err(e) = self.try_load_asm_content(p) {
                     warn!("Failed to load assembly: {:?}", e);
                 }
@@ -1013,7 +2660,21 @@ impl<'a> CodeWindow<'a> {
     }
 
     fn find_function_range(at: Address, p: ::UpdateParameters) -> Result<(Address, Address), ()> {
-        let first_lines = disassemble_address(at, at + 16, p).map_err(|_| ())?;
+        if let Some(range) = p.gdb.disassembly_cache.function_range_containing(at) {
+            return Ok(range);
+        }
+        let range = Self::disassemble_function_range(at, p)?;
+        p.gdb
+            .disassembly_cache
+            .insert_function_range(range.0, range.1);
+        Ok(range)
+    }
+    fn disassemble_function_range(
+        at: Address,
+        p: ::UpdateParameters,
+    ) -> Result<(Address, Address), ()> {
+        let first_lines = disassemble_address(at, at + 16, DisassembleMode::DisassemblyOnly, p)
+            .map_err(|_| ())?;
         let current = first_lines.first().ok_or(())?;
         let asm_debug_location = AssemblyDebugLocation::try_from_value(current).ok_or(())?;
         let begin = at - asm_debug_location.offset;
@@ -1021,8 +2682,13 @@ impl<'a> CodeWindow<'a> {
         let block_size = 128;
         let mut current = at;
         let func_change_block = loop {
-            let current_block_lines =
-                disassemble_address(current, current + block_size, p).map_err(|_| ())?;
+            let current_block_lines = disassemble_address(
+                current,
+                current + block_size,
+                DisassembleMode::DisassemblyOnly,
+                p,
+            )
+            .map_err(|_| ())?;
             {
                 let penultimate_index = current_block_lines.len().checked_sub(2).ok_or(())?;
                 let penultimate = current_block_lines
@@ -1051,7 +2717,12 @@ impl<'a> CodeWindow<'a> {
         approx_byte_size: usize,
         p: ::UpdateParameters,
     ) -> Result<(Address, Address), DisassembleError> {
-        let block_lines = disassemble_address(at, at + approx_byte_size, p)?;
+        let block_lines = disassemble_address(
+            at,
+            at + approx_byte_size,
+            DisassembleMode::DisassemblyOnly,
+            p,
+        )?;
 
         let penultimate_index = block_lines
             .len()
@@ -1072,11 +2743,14 @@ impl<'a> CodeWindow<'a> {
 
         self.src_state = SrcContentState::Unavailable;
         self.asm_state = AsmContentState::Unavailable;
+        self.pending_jump = None;
+        self.evaluation_popup = None;
 
         self.stack_info.stack_level = p.gdb.get_stack_level().ok();
         self.stack_info.stack_depth = p.gdb.get_stack_depth().ok();
         self.stack_info.file_path = frame["fullname"].as_str().map(|s| PathBuf::from(s));
         self.stack_info.function = frame["func"].as_str().map(|s| s.to_owned());
+        self.sync_record_state(p);
 
         if let Some(path) = frame["fullname"].as_str() {
             let path = PathBuf::from(path);
@@ -1091,6 +2765,7 @@ impl<'a> CodeWindow<'a> {
                     let line = LineNumber::new(line as usize);
 
                     self.src_view.set_last_stop_position(path.clone(), line);
+                    self.src_view.update_variable_values(p);
 
                     self.asm_state = if self
                         .asm_view
@@ -1140,6 +2815,84 @@ impl<'a> CodeWindow<'a> {
         let _ = self.src_view.go_to_last_stop_position();
         self.asm_view.update_decoration(p);
         self.src_view.update_decoration(p);
+        self.src_view.update_cursor();
+        self.asm_view.update_register_annotations(p);
+    }
+
+    // Show a location that was requested externally (e.g., via ipc), independent of the current
+    // frame. Assembly is only loaded on demand, because the file may not be part of the program.
+    pub fn show_location(&mut self, path: PathBuf, line: LineNumber, p: ::UpdateParameters) {
+        match self.preferred_mode {
+            DisplayMode::Source | DisplayMode::SideBySide => {}
+            _ => self.preferred_mode = DisplayMode::Source,
+        }
+        self.pending_jump = None;
+        self.evaluation_popup = None;
+        if self.src_view.current_file() != Some(path.as_path()) {
+            self.src_state = SrcContentState::NotYetLoaded(path.clone());
+        }
+        self.asm_state = AsmContentState::NotYetLoadedFile(path, line.into());
+        self.try_load_active_content(p);
+        let _ = self.src_view.go_to_line(line);
+        self.src_view.update_cursor();
+    }
+
+    fn sync_record_state(&mut self, p: ::UpdateParameters) {
+        let record = &p.gdb.record;
+        self.stack_info.record_state = Some(record.state);
+        self.stack_info.record_method = record.method.clone();
+        self.stack_info.replay_event = record.current_event;
+        self.stack_info.replay_tick = record.current_tick;
+        if record.is_replaying() {
+            self.asm_view.set_live_position(record.live_address);
+            self.src_view
+                .set_live_position(record.live_src_position.clone());
+        } else {
+            self.asm_view.set_live_position(None);
+            self.src_view.set_live_position(None);
+        }
+    }
+
+    pub fn update_record_state(&mut self, p: ::UpdateParameters) {
+        self.sync_record_state(p);
+        self.asm_view.update_decoration(p);
+        self.src_view.update_decoration(p);
+    }
+
+    // Forward execution is controlled from the console, but reverse execution has no short
+    // commands there.
+    fn reverse_execution(&mut self, command: ExecutionCommand, p: ::UpdateParameters) {
+        // Step by instructions if assembly is shown (and navigated).
+        let instruction_wise = match self.available_display_mode() {
+            DisplayMode::Assembly | DisplayMode::SideBySide | DisplayMode::Interleaved => true,
+            DisplayMode::Source | DisplayMode::Message(_) => false,
+        };
+        let command = match (command, instruction_wise) {
+            (ExecutionCommand::Next, false) => MiCommand::exec_next(true),
+            (ExecutionCommand::Next, true) => MiCommand::exec_next_instruction(true),
+            (ExecutionCommand::Step, false) => MiCommand::exec_step(true),
+            (ExecutionCommand::Step, true) => MiCommand::exec_step_instruction(true),
+            (ExecutionCommand::Continue, _) => MiCommand::exec_continue(true),
+            (ExecutionCommand::Finish, _) => MiCommand::exec_finish(true),
+        };
+        match p.gdb.mi.execute(command) {
+            Ok(ResultRecord {
+                class: ResultClass::Error,
+                results,
+                ..
+            }) => {
+                p.message_sink.send(
+                    results["msg"]
+                        .as_str()
+                        .unwrap_or("Unknown error")
+                        .to_owned(),
+                );
+            }
+            Ok(_) => {}
+            Err(_) => {
+                p.message_sink.send("Gdb is busy.");
+            }
+        }
     }
 
     fn toggle_mode(&mut self, p: ::UpdateParameters) {
@@ -1147,7 +2900,8 @@ impl<'a> CodeWindow<'a> {
         let prev_mode = self.preferred_mode.clone();
         self.preferred_mode = match prev_mode {
             DisplayMode::Assembly => DisplayMode::Source,
-            DisplayMode::SideBySide => DisplayMode::Assembly,
+            DisplayMode::Interleaved => DisplayMode::Assembly,
+            DisplayMode::SideBySide => DisplayMode::Interleaved,
             DisplayMode::Source => {
                 sync_asm_to_src = true;
                 DisplayMode::SideBySide
@@ -1178,6 +2932,144 @@ impl<'a> CodeWindow<'a> {
                 }
             }
         }
+        let interleaved = self.preferred_mode == DisplayMode::Interleaved;
+        self.asm_view.set_interleaved(interleaved, p);
+    }
+
+    fn current_jump_target(&self) -> Option<JumpTarget> {
+        match self.available_display_mode() {
+            DisplayMode::Assembly | DisplayMode::SideBySide | DisplayMode::Interleaved => self
+                .asm_view
+                .pager
+                .current_line()
+                .map(|line| JumpTarget::Address(line.address)),
+            DisplayMode::Source => self
+                .src_view
+                .current_file()
+                .map(|path| JumpTarget::Line(path.to_owned(), self.src_view.current_line_number())),
+            DisplayMode::Message(_) => None,
+        }
+    }
+
+    fn jump_to_cursor(&mut self, p: ::UpdateParameters) {
+        let target = if let Some(target) = self.current_jump_target() {
+            target
+        } else {
+            return;
+        };
+        if p.gdb.mi.is_running() {
+            p.message_sink.send("Cannot jump: Gdb is busy.");
+            return;
+        }
+
+        // Jumping to another function is most likely a mistake, so we want the user to confirm
+        // by trying again.
+        let confirmed = self.pending_jump.take() == Some(target.clone());
+        if !confirmed {
+            let target_function = target.function_name(p).unwrap_or(None);
+            if target_function.is_none() || target_function != self.stack_info.function {
+                p.message_sink.send(format!(
+                    "Jump target lies outside of the current function (in {}). Press 'J' again to jump anyway.",
+                    target_function.as_ref().map(|f| f.as_str()).unwrap_or("?")
+                ));
+                self.pending_jump = Some(target);
+                return;
+            }
+        }
+
+        // -exec-jump resumes execution, so we stop right at the target using a temporary
+        // breakpoint. The frame display is then updated with the resulting stop record.
+        let breakpoint = match p.gdb.insert_temporary_breakpoint(target.location()) {
+            Ok(number) => number,
+            Err(BreakpointOperationError::Busy) => {
+                p.message_sink.send("Cannot jump: Gdb is busy.");
+                return;
+            }
+            Err(BreakpointOperationError::ExecutionError(msg)) => {
+                p.message_sink.send(format!("Cannot jump: {}", msg));
+                return;
+            }
+        };
+        let error = match p.gdb.mi.execute(MiCommand::exec_jump(target.location())) {
+            Ok(ResultRecord {
+                class: ResultClass::Error,
+                results,
+                ..
+            }) => results["msg"]
+                .as_str()
+                .unwrap_or("Unknown error")
+                .to_owned(),
+            Ok(_) => return,
+            Err(ExecuteError::Busy) => "Gdb is busy.".to_owned(),
+            Err(ExecuteError::Quit) => panic!("Could not jump: GDB quit"),
+        };
+        p.message_sink.send(format!("Cannot jump: {}", error));
+        // The temporary breakpoint would otherwise surprise the user later on.
+        match p.gdb.delete_breakpoints(::std::iter::once(breakpoint)) {
+            Ok(()) => {}
+            Err(BreakpointOperationError::Busy) => {
+                warn!("Failed to remove temporary jump breakpoint: Gdb is busy")
+            }
+            Err(BreakpointOperationError::ExecutionError(msg)) => {
+                warn!("Failed to remove temporary jump breakpoint: {}", msg)
+            }
+        }
+    }
+
+    fn evaluate_expression_under_cursor(&mut self, p: ::UpdateParameters) {
+        let expression = if let Some(expression) = self.src_view.expression_under_cursor() {
+            expression
+        } else {
+            p.message_sink.send("No expression under the cursor.");
+            return;
+        };
+        match p.gdb.evaluate_expression(expression.clone()) {
+            Ok(value) => {
+                self.evaluation_popup = Some(EvaluationPopup::new(expression, &value));
+            }
+            Err(EvaluationError::Gdb(msg)) => {
+                p.message_sink
+                    .send(format!("Cannot evaluate '{}': {}", expression, msg));
+            }
+            Err(EvaluationError::Execution(ExecuteError::Busy)) => {
+                p.message_sink
+                    .send(format!("Cannot evaluate '{}': Gdb is busy.", expression));
+            }
+            Err(EvaluationError::Execution(ExecuteError::Quit)) => {
+                panic!("GDB quit!");
+            }
+        }
+    }
+
+    fn evaluation_popup_event(&mut self, input: Input, p: ::UpdateParameters) -> Option<Input> {
+        let mut close = false;
+        let ret = if let Some(ref mut popup) = self.evaluation_popup {
+            let expression = popup.expression.clone();
+            input
+                .chain((Key::Char('q'), || close = true))
+                .chain((Key::Char('a'), || {
+                    p.ui_request_sink
+                        .send(UIRequest::AddWatchExpression(expression));
+                    close = true;
+                }))
+                .chain(
+                    ScrollBehavior::new(&mut popup.viewer)
+                        .forwards_on(Key::Down)
+                        .forwards_on(Key::Char('j'))
+                        .backwards_on(Key::Up)
+                        .backwards_on(Key::Char('k')),
+                )
+                .chain((Key::Char(' '), || {
+                    let _ = popup.viewer.toggle_active_element();
+                }))
+                .finish()
+        } else {
+            Some(input)
+        };
+        if close {
+            self.evaluation_popup = None;
+        }
+        ret
     }
 
     fn try_switch_stackframe(
@@ -1235,6 +3127,10 @@ impl<'a> CodeWindow<'a> {
             self.src_view.update_decoration(p);
             self.last_bp_update = p.gdb.breakpoints.last_change;
         }
+        if p.annotations.last_change > self.last_annotation_update {
+            self.src_view.refresh_content(p);
+            self.last_annotation_update = p.annotations.last_change;
+        }
     }
 }
 
@@ -1242,12 +3138,21 @@ impl<'a> Widget for CodeWindow<'a> {
     fn space_demand(&self) -> Demand2D {
         let mode = self.available_display_mode();
         let main_demand = match &mode {
-            DisplayMode::Assembly => self.asm_view.space_demand(),
+            DisplayMode::Assembly | DisplayMode::Interleaved => self.asm_view.space_demand(),
             DisplayMode::SideBySide => self.layout.space_demand(&[&self.asm_view, &self.src_view]),
             DisplayMode::Source => self.src_view.space_demand(),
             DisplayMode::Message(ref m) => MsgWindow::new(&m).space_demand(),
         };
-        if let DisplayMode::Assembly | DisplayMode::Source | DisplayMode::SideBySide = mode {
+        let main_demand = if let Some(ref popup) = self.evaluation_popup {
+            main_demand.add_vertical(popup.space_demand())
+        } else {
+            main_demand
+        };
+        if let DisplayMode::Assembly
+        | DisplayMode::Source
+        | DisplayMode::SideBySide
+        | DisplayMode::Interleaved = mode
+        {
             main_demand.add_vertical(self.stack_info.space_demand())
         } else {
             main_demand
@@ -1255,20 +3160,35 @@ impl<'a> Widget for CodeWindow<'a> {
     }
     fn draw(&self, window: Window, hints: RenderingHints) {
         let mode = self.available_display_mode();
-        let window =
-            if let DisplayMode::Assembly | DisplayMode::SideBySide | DisplayMode::Source = mode {
-                match window.split(RowIndex::new(1)) {
-                    Ok((top, window)) => {
-                        self.stack_info.draw(top, hints);
-                        window
-                    }
-                    Err(window) => window,
+        let window = if let DisplayMode::Assembly
+        | DisplayMode::SideBySide
+        | DisplayMode::Source
+        | DisplayMode::Interleaved = mode
+        {
+            match window.split(RowIndex::new(1)) {
+                Ok((top, window)) => {
+                    self.stack_info.draw(top, hints);
+                    window
                 }
-            } else {
-                window
-            };
+                Err(window) => window,
+            }
+        } else {
+            window
+        };
+        let window = if let Some(ref popup) = self.evaluation_popup {
+            let split_row = window.get_height().raw_value() - popup.height_in(window.get_height());
+            match window.split(RowIndex::new(split_row)) {
+                Ok((window, bottom)) => {
+                    popup.draw(bottom, hints);
+                    window
+                }
+                Err(window) => window,
+            }
+        } else {
+            window
+        };
         match mode {
-            DisplayMode::Assembly => self.asm_view.draw(window, hints),
+            DisplayMode::Assembly | DisplayMode::Interleaved => self.asm_view.draw(window, hints),
             DisplayMode::SideBySide => self.layout.draw(
                 window,
                 &[
@@ -1285,11 +3205,32 @@ impl<'a> Widget for CodeWindow<'a> {
 impl<'a> Container<::UpdateParametersStruct> for CodeWindow<'a> {
     fn input(&mut self, input: Input, p: ::UpdateParameters) -> Option<Input> {
         input
+            .chain(|i: Input| self.evaluation_popup_event(i, p))
             .chain((Key::Char('d'), || self.toggle_mode(p)))
+            .chain((Key::Char('J'), || self.jump_to_cursor(p)))
+            .chain((Key::Char('N'), || {
+                self.reverse_execution(ExecutionCommand::Next, p)
+            }))
+            .chain((Key::Char('S'), || {
+                self.reverse_execution(ExecutionCommand::Step, p)
+            }))
+            .chain((Key::Char('C'), || {
+                self.reverse_execution(ExecutionCommand::Continue, p)
+            }))
+            .chain((Key::Char('F'), || {
+                self.reverse_execution(ExecutionCommand::Finish, p)
+            }))
+            .chain(|i: Input| match self.available_display_mode() {
+                DisplayMode::Source if i.matches(Key::Char('K')) => {
+                    self.evaluate_expression_under_cursor(p);
+                    None
+                }
+                _ => Some(i),
+            })
             .chain((Key::PageUp, || self.switch_stackframe(p, true)))
             .chain((Key::PageDown, || self.switch_stackframe(p, false)))
             .chain(|i: Input| match self.available_display_mode() {
-                DisplayMode::Assembly | DisplayMode::SideBySide => {
+                DisplayMode::Assembly | DisplayMode::SideBySide | DisplayMode::Interleaved => {
                     let ret = self.asm_view.event(i, p);
                     if let Some(src_pos) = self
                         .asm_view
@@ -1300,6 +3241,7 @@ impl<'a> Container<::UpdateParametersStruct> for CodeWindow<'a> {
                         self.src_state = SrcContentState::NotYetLoaded(src_pos.file.to_path_buf());
                         self.try_load_active_content(p);
                         let _ = self.src_view.go_to_line(src_pos.line);
+                        self.src_view.update_cursor();
                     }
                     ret
                 }
@@ -1,14 +1,35 @@
 use unsegen_pager::Theme;
 
-use gdbmi::output::{AsyncClass, AsyncKind, JsonValue, Object, OutOfBandRecord, ThreadEvent};
+use gdb::response::get_u64_obj;
+use gdb::ConnectionError;
+use gdbmi::commands::{MiCommand, TargetType};
+use gdbmi::output::{
+    AsyncClass, AsyncKind, JsonValue, Object, OutOfBandRecord, ResultClass, ThreadEvent,
+};
+use std::ffi::OsStr;
+use std::path::Path;
 
 use super::console::Console;
 use super::expression_table::ExpressionTable;
 use super::srcview::CodeWindow;
 use log::{debug, info};
+use unsegen::base::LineNumber;
 use unsegen::container::{Container, ContainerProvider};
 use unsegen_terminal::Terminal;
 
+fn follow_in_editor(frame: &Object, p: ::UpdateParameters) {
+    if let (Some(file), Some(line)) = (frame["fullname"].as_str(), get_u64_obj(frame, "line").ok())
+    {
+        if let Err(e) = p
+            .editor
+            .follow(Path::new(file), LineNumber::new(line as usize))
+        {
+            p.message_sink
+                .send(format!("Unable to run editor follow command: {}", e));
+        }
+    }
+}
+
 pub struct Tui<'a> {
     pub console: Console,
     expression_table: ExpressionTable,
@@ -48,12 +69,28 @@ impl<'a> Tui<'a> {
         results: &Object,
         p: ::UpdateParameters,
     ) {
+        let is_stop = class == AsyncClass::Stopped;
         match (kind, class) {
             (AsyncKind::Exec, AsyncClass::Stopped)
             | (AsyncKind::Notify, AsyncClass::Thread(ThreadEvent::Selected)) => {
                 debug!("stopped: {}", JsonValue::Object(results.clone()).pretty(2));
+                if is_stop {
+                    p.gdb.record.handle_stop(results);
+                    if p.gdb.record.replay_only {
+                        if let Err(e) = p.gdb.query_replay_progress() {
+                            info!("Unable to query replay progress: {:?}", e);
+                        }
+                    } else if p.gdb.record.needs_state_query() {
+                        if let Err(e) = p.gdb.query_record_state() {
+                            info!("Unable to query record state: {:?}", e);
+                        }
+                    }
+                }
                 if let JsonValue::Object(ref frame) = results["frame"] {
                     self.src_view.show_frame(frame, p);
+                    if is_stop {
+                        follow_in_editor(frame, p);
+                    }
                 }
                 self.expression_table.update_results(p);
             }
@@ -65,6 +102,23 @@ impl<'a> Tui<'a> {
                 );
                 p.gdb.handle_breakpoint_event(event, &results);
             }
+            (AsyncKind::Notify, AsyncClass::LibraryLoaded)
+            | (AsyncKind::Notify, AsyncClass::LibraryUnloaded) => {
+                p.gdb.disassembly_cache.invalidate();
+            }
+            (AsyncKind::Notify, AsyncClass::RecordStarted) => {
+                p.gdb.record.handle_record_started(results);
+                self.src_view.update_record_state(p);
+            }
+            (AsyncKind::Notify, AsyncClass::RecordStopped) => {
+                p.gdb.record.handle_record_stopped();
+                self.src_view.update_record_state(p);
+            }
+            (AsyncKind::Notify, AsyncClass::CmdParamChanged)
+                if results["param"] == "disassembly-flavor" =>
+            {
+                p.gdb.disassembly_cache.invalidate();
+            }
             (kind, class) => {
                 info!(
                     "unhandled async_record: [{:?}, {:?}] {}",
@@ -77,9 +131,14 @@ impl<'a> Tui<'a> {
     }
 
     pub fn add_out_of_band_record(&mut self, record: OutOfBandRecord, p: ::UpdateParameters) {
+        p.event_subscriptions.handle_out_of_band_record(&record);
         match record {
             OutOfBandRecord::StreamRecord { kind: _, data } => {
-                self.console.write_to_gdb_log(data);
+                if p.gdb.record.handle_console_output(&data) {
+                    self.src_view.update_record_state(p);
+                } else {
+                    self.console.write_to_gdb_log(data);
+                }
             }
             OutOfBandRecord::AsyncRecord {
                 token: _,
@@ -92,11 +151,69 @@ impl<'a> Tui<'a> {
         }
     }
 
+    pub fn connect_to_target(
+        &mut self,
+        target_type: TargetType,
+        address: &OsStr,
+        p: ::UpdateParameters,
+    ) {
+        match p.gdb.connect_to_target(target_type, address) {
+            Ok(()) => {
+                p.message_sink.send(format!(
+                    "Connected to {} target {}.",
+                    target_type.as_str(),
+                    address.to_string_lossy()
+                ));
+                // Gdb does not report a stop when connecting, so we look up the location
+                // ourselves.
+                if let Ok(frame_info) = p.gdb.mi.execute(MiCommand::stack_info_frame(None)) {
+                    if let (ResultClass::Done, JsonValue::Object(ref frame)) =
+                        (frame_info.class, &frame_info.results["frame"])
+                    {
+                        self.src_view.show_frame(frame, p);
+                        self.expression_table.update_results(p);
+                    }
+                }
+            }
+            Err(ConnectionError::Gdb(msg)) => {
+                p.message_sink
+                    .send(format!("Unable to connect to target: {}", msg));
+            }
+            Err(ConnectionError::Execution(e)) => {
+                p.message_sink
+                    .send(format!("Unable to connect to target: {:?}", e));
+            }
+        }
+    }
+
     pub fn add_pty_input(&mut self, input: &[u8]) {
         self.process_pty.add_byte_input(input);
     }
 
+    fn handle_ui_request(&mut self, request: ::UIRequest, p: ::UpdateParameters) {
+        match request {
+            ::UIRequest::AddWatchExpression(expression) => {
+                self.expression_table.add_expression(expression, p);
+            }
+            ::UIRequest::ShowLocation(path, line) => {
+                self.src_view.show_location(path, line, p);
+            }
+            ::UIRequest::ShowProcessPicker => {
+                self.console.show_process_picker();
+            }
+            ::UIRequest::ClearTerminal => {
+                // Clear the screen and move the cursor to the top left corner.
+                self.process_pty.add_byte_input(b"\x1b[2J\x1b[H");
+            }
+        }
+    }
+
     pub fn update_after_event(&mut self, p: ::UpdateParameters) {
+        for request in p.ui_request_sink.drain_requests() {
+            self.handle_ui_request(request, p);
+        }
+        p.event_subscriptions
+            .publish_breakpoint_changes(&mut p.gdb.breakpoints);
         self.src_view.update_after_event(p);
         self.console.update_after_event(p);
     }